@@ -0,0 +1,59 @@
+//! Times `samples/mandelbrot.bf` on each backend, best of `RUNS`, after checking that
+//! every backend prints the expected picture. Run with `cargo bench --bench mandelbrot`.
+use std::{
+    fs,
+    time::{Duration, Instant},
+};
+use bf_rs::{
+    interpreter::Interpreter,
+    io::{NullWriter, Zeros},
+};
+
+const RUNS: usize = 3;
+
+fn best(mut run: impl FnMut()) -> Duration {
+    (0..RUNS).map(|_| {
+        let start = Instant::now();
+        run();
+        start.elapsed()
+    }).min().unwrap()
+}
+
+fn main() {
+    let samples = concat!(env!("CARGO_MANIFEST_DIR"), "/samples");
+    let src = fs::read_to_string(format!("{}/mandelbrot.bf", samples)).unwrap();
+    let expected = fs::read(format!("{}/mandelbrot.out", samples)).unwrap();
+    let program = Interpreter::new(src.chars()).unwrap();
+    let threaded = program.threaded().unwrap();
+    let compiled = program.compile();
+
+    let mut output = Vec::new();
+    program.run(&mut Zeros, &mut output).unwrap();
+    assert!(output == expected, "run printed the wrong picture");
+    output.clear();
+    threaded.run(&mut Zeros, &mut output).unwrap();
+    assert!(output == expected, "threaded printed the wrong picture");
+    output.clear();
+    compiled(&mut Zeros, &mut output);
+    assert!(output == expected, "jit printed the wrong picture");
+
+    // `NullWriter` keeps the timings to the program's own work, and its count checks
+    // that each timed run printed the whole picture.
+    let timed = |run: &dyn Fn(&mut NullWriter)| best(|| {
+        let mut output = NullWriter::new();
+        run(&mut output);
+        assert_eq!(output.written(), expected.len() as u64);
+    });
+    let timings = [
+        ("run", timed(&|output| {
+            program.run(&mut Zeros, output).unwrap();
+        })),
+        ("threaded", timed(&|output| {
+            threaded.run(&mut Zeros, output).unwrap();
+        })),
+        ("jit", timed(&|output| compiled(&mut Zeros, output))),
+    ];
+    for (backend, time) in timings {
+        println!("mandelbrot {:<10} {:>8.3}s", backend, time.as_secs_f64());
+    }
+}
\ No newline at end of file
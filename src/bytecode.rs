@@ -0,0 +1,198 @@
+use std::convert::TryFrom;
+use crate::interpreter::{Interpreter, Token};
+
+const ADD: u8 = 0;
+const MUL: u8 = 1;
+const ADD_TO: u8 = 2;
+const CLEAR: u8 = 3;
+const SHIFT: u8 = 4;
+const LOOP_BEGIN: u8 = 5;
+const LOOP_END: u8 = 6;
+const INPUT: u8 = 7;
+const OUTPUT: u8 = 8;
+const END: u8 = 9;
+const SET: u8 = 10;
+const OUTPUT_BYTES: u8 = 11;
+const SCAN: u8 = 12;
+
+impl Interpreter {
+    /// Encodes the optimized tokens as a tag byte each followed by zigzag LEB128
+    /// operands. Loop operands are left out since `decode` relinks them. The tokens are
+    /// the ones that hold on any tape, so the program `decode` optimizes again from them
+    /// still runs correctly on a seeded one.
+    pub fn encode(&self) -> Vec<u8> {
+        encode_tokens(self.any_tape_tokens())
+    }
+
+    /// A hash of the optimized tokens that is stable across runs and builds, so programs
+    /// differing only in comments or in what the optimizer folds away hash the same.
+    /// FNV-1a over `encode`; not meant to resist deliberate collisions.
+    pub fn canonical_hash(&self) -> u64 {
+        self.encode().iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+            (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+        })
+    }
+
+    /// Loads a program written by `encode` without parsing it again.
+    pub fn decode(bytes: &[u8]) -> Result<Self, &'static str> {
+        Self::from_tokens(decode_tokens(bytes)?)
+    }
+}
+
+/// The `encode` format of `tokens`, taken as they are.
+pub(crate) fn encode_tokens(tokens: &[Token]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for token in tokens {
+        match *token {
+            Token::Add(n, shift) => {
+                out.push(ADD);
+                write_varint(&mut out, n as i32);
+                write_varint(&mut out, shift);
+            }
+            Token::Mul(n, shift, base) => {
+                out.push(MUL);
+                write_varint(&mut out, n as i32);
+                write_varint(&mut out, shift);
+                write_varint(&mut out, base);
+            }
+            Token::AddTo(to, from) => {
+                out.push(ADD_TO);
+                write_varint(&mut out, to);
+                write_varint(&mut out, from);
+            }
+            Token::Clear(shift) => {
+                out.push(CLEAR);
+                write_varint(&mut out, shift);
+            }
+            Token::Set(n, shift) => {
+                out.push(SET);
+                write_varint(&mut out, n as i32);
+                write_varint(&mut out, shift);
+            }
+            Token::Shift(shift) => {
+                out.push(SHIFT);
+                write_varint(&mut out, shift);
+            }
+            Token::LoopBegin(_) => out.push(LOOP_BEGIN),
+            Token::LoopEnd(_) => out.push(LOOP_END),
+            Token::Input(shift) => {
+                out.push(INPUT);
+                write_varint(&mut out, shift);
+            }
+            Token::Output(shift) => {
+                out.push(OUTPUT);
+                write_varint(&mut out, shift);
+            }
+            Token::OutputBytes(ref literal) => {
+                out.push(OUTPUT_BYTES);
+                write_varint(&mut out, literal.len() as i32);
+                out.extend_from_slice(literal);
+            }
+            Token::Scan(n) => {
+                out.push(SCAN);
+                write_varint(&mut out, n);
+            }
+            Token::Nop => {}
+            Token::End => out.push(END),
+        }
+    }
+    out
+}
+
+/// The tokens `encode` wrote, loop operands still zero.
+pub(crate) fn decode_tokens(bytes: &[u8]) -> Result<Vec<Token>, &'static str> {
+    let mut bytes = bytes.iter().copied();
+    let mut inst = Vec::new();
+    while let Some(tag) = bytes.next() {
+        let mut operand = || read_varint(&mut bytes);
+        let token = match tag {
+            ADD => Token::Add(narrow(operand()?)?, operand()?),
+            MUL => Token::Mul(narrow(operand()?)?, operand()?, operand()?),
+            ADD_TO => Token::AddTo(operand()?, operand()?),
+            CLEAR => Token::Clear(operand()?),
+            SET => Token::Set(narrow(operand()?)?, operand()?),
+            SHIFT => Token::Shift(operand()?),
+            LOOP_BEGIN => Token::LoopBegin(0),
+            LOOP_END => Token::LoopEnd(0),
+            INPUT => Token::Input(operand()?),
+            OUTPUT => Token::Output(operand()?),
+            OUTPUT_BYTES => {
+                let len = operand()?;
+                let literal: Vec<u8> = bytes.by_ref().take(len.max(0) as usize).collect();
+                if literal.len() != len as usize {
+                    return Err("truncated bytecode.");
+                }
+                Token::OutputBytes(literal)
+            }
+            SCAN => Token::Scan(operand()?),
+            END => Token::End,
+            _ => return Err("unknown bytecode tag."),
+        };
+        inst.push(token);
+    }
+    Ok(inst)
+}
+
+pub(crate) fn write_varint(out: &mut Vec<u8>, n: i32) {
+    let mut n = ((n << 1) ^ (n >> 31)) as u32;
+    while n >= 0x80 {
+        out.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+pub(crate) fn read_varint<I: Iterator<Item=u8>>(bytes: &mut I) -> Result<i32, &'static str> {
+    let mut n = 0u32;
+    for shift in (0..35).step_by(7) {
+        let byte = bytes.next().ok_or("truncated bytecode.")?;
+        n |= ((byte & 0x7f) as u32).checked_shl(shift).unwrap_or(0);
+        if byte & 0x80 == 0 {
+            return Ok((n >> 1) as i32 ^ -((n & 1) as i32));
+        }
+    }
+    Err("bytecode operand out of range.")
+}
+
+fn narrow<T: TryFrom<i32>>(n: i32) -> Result<T, &'static str> {
+    T::try_from(n).map_err(|_| "bytecode operand out of range.")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use crate::tape::Tape;
+    use super::*;
+
+    #[test]
+    fn decode_restores_tokens() {
+        let program = Interpreter::new("++[->+++<]>.<,[-]+.+.".chars()).unwrap();
+        let decoded = Interpreter::decode(&program.encode()).unwrap();
+        assert_eq!(decoded.tokens(), program.tokens());
+    }
+
+    #[test]
+    fn decoded_program_runs_on_seeded_tape() {
+        let program = Interpreter::decode(&Interpreter::new("+.+.".chars()).unwrap().encode()).unwrap();
+        let mut output = Vec::new();
+        program.run_on(Tape::from_cells(&[65]), &mut io::empty(), &mut output).unwrap();
+        assert_eq!(output, [66, 67]);
+        assert_eq!(program.run_bytes(b"").unwrap(), [1, 2]);
+    }
+
+    #[test]
+    fn varints_round_trip() {
+        for &n in &[0, 1, -1, 63, -64, 64, 1 << 20, i32::MAX, i32::MIN] {
+            let mut out = Vec::new();
+            write_varint(&mut out, n);
+            assert_eq!(read_varint(&mut out.into_iter()).unwrap(), n);
+        }
+    }
+
+    #[test]
+    fn truncated_bytecode_is_an_error() {
+        let bytes = Interpreter::new("+++>.".chars()).unwrap().encode();
+        assert!(decode_tokens(&bytes[..bytes.len() - 2]).is_err());
+        assert!(Interpreter::decode(&[0xff]).is_err());
+    }
+}
@@ -0,0 +1,87 @@
+/// Writes the shortest way found to add `delta` to the current cell: plainly, or as
+/// a multiply loop over the zeroed cell to its right plus a remainder.
+fn push_add(out: &mut String, delta: i32) {
+    let sign = if delta < 0 { "-" } else { "+" };
+    let n = delta.unsigned_abs() as usize;
+    let mut best = None;
+    for a in 2..=n / 2 {
+        let (b, r) = (n / a, n % a);
+        let cost = a + b + r + 7;
+        if cost < best.map_or(n, |(cost, _, _)| cost) {
+            best = Some((cost, a, b));
+        }
+    }
+    match best {
+        Some((_, a, b)) => {
+            out.push('>');
+            out.push_str(&"+".repeat(a));
+            out.push_str("[<");
+            out.push_str(&sign.repeat(b));
+            out.push_str(">-]<");
+            out.push_str(&sign.repeat(n - a * b));
+        }
+        None => out.push_str(&sign.repeat(n)),
+    }
+}
+
+/// A program printing `text` as UTF-8. Every byte is built in one cell from the one
+/// before it, going up or down whichever way is shorter modulo 256.
+pub fn gen_print(text: &str) -> String {
+    let mut out = String::new();
+    let mut cell = 0u8;
+    for &byte in text.as_bytes() {
+        let up = byte.wrapping_sub(cell) as i32;
+        push_add(&mut out, if up > 128 { up - 256 } else { up });
+        out.push('.');
+        cell = byte;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{interpreter::Interpreter, testing::interpret_naive};
+
+    /// `+` and `-` from one byte to the next, the way a naive generator would.
+    fn naive(text: &str) -> String {
+        let mut out = String::new();
+        let mut cell = 0u8;
+        for &byte in text.as_bytes() {
+            let up = byte.wrapping_sub(cell) as i32;
+            let delta = if up > 128 { up - 256 } else { up };
+            out.push_str(&(if delta < 0 { "-" } else { "+" }).repeat(delta.unsigned_abs() as usize));
+            out.push('.');
+            cell = byte;
+        }
+        out
+    }
+
+    #[test]
+    fn printed_text_round_trips() {
+        for text in ["", "A", "Hello, World!\n", "zzz", "ÿ\u{0}~ é", "\u{1F980} crab"] {
+            let src = gen_print(text);
+            assert_eq!(interpret_naive(&src, b"", 1 << 20).unwrap(), text.as_bytes(), "{:?}", text);
+            assert_eq!(Interpreter::new(src.chars()).unwrap().run_bytes(b"").unwrap(), text.as_bytes(), "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn every_byte_step_round_trips() {
+        for delta in -128..=128 {
+            let mut out = "+".repeat(128);
+            push_add(&mut out, delta);
+            out.push('.');
+            assert_eq!(interpret_naive(&out, b"", 1 << 16).unwrap(), [(128 + delta) as u8], "{}", delta);
+        }
+    }
+
+    #[test]
+    fn shorter_than_naive() {
+        for text in ["Hello, World!\n", "The quick brown fox jumps over the lazy dog.", "AAAA"] {
+            assert!(gen_print(text).len() <= naive(text).len(), "{:?}", text);
+        }
+        let text = "Hello, World!\n";
+        assert!(gen_print(text).len() * 3 < naive(text).len() * 2, "{} vs {}", gen_print(text).len(), naive(text).len());
+    }
+}
\ No newline at end of file
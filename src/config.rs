@@ -0,0 +1,55 @@
+use std::time::Duration;
+use crate::{
+    cancel::CancelHandle,
+    io::{DebugWriter, EofPolicy, FlushPolicy},
+    tape::Tape,
+};
+
+/// How `Add`, `Mul` and `AddTo` treat a cell going past 0 or 255.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CellArith {
+    #[default]
+    Wrapping,
+    /// Clamps at 0 and 255. Only exact for programs from `Interpreter::saturating`,
+    /// since the optimizer folds `+` and `-` runs as if cells wrapped.
+    Saturating,
+}
+
+/// Options for one run, accepted by both `Interpreter::run_with` and
+/// `Interpreter::compile_with`. The JIT honours `flush`, `eof`, `arith`, `tape`,
+/// `bounds_check` and `reset_pointer`; the rest are checked by the interpreter alone,
+/// since compiled code never stops to poll them, and `Interpreter::execute` interprets
+/// whenever one of them is set.
+#[derive(Debug, Clone, Default)]
+pub struct ExecConfig {
+    pub flush: FlushPolicy,
+    pub eof: EofPolicy,
+    pub arith: CellArith,
+    /// Starting cells and pointer; a blank tape when `None`. Only the interpreter grows
+    /// a `Tape::growable`.
+    pub tape: Option<Tape>,
+    /// Make compiled code check every cell access; the interpreter always does.
+    pub bounds_check: bool,
+    /// Leave the pointer on cell 0 when the program ends, for programs run as
+    /// subroutines on a shared tape.
+    pub reset_pointer: bool,
+    /// Print each executed token to `debug`.
+    pub trace: bool,
+    /// Where `trace` prints; stderr unless replaced.
+    pub debug: DebugWriter,
+    /// Fail with `ExecError::StepLimit` after this many tokens.
+    pub max_steps: Option<u64>,
+    /// Fail with `ExecError::TimedOut` once this much time has passed.
+    pub timeout: Option<Duration>,
+    /// Fail with `ExecError::Cancelled` soon after the handle is triggered.
+    pub cancel: Option<CancelHandle>,
+}
+
+impl ExecConfig {
+    /// Whether any option is set that only the interpreter checks.
+    pub fn needs_interpreter(&self) -> bool {
+        self.trace || self.max_steps.is_some() || self.timeout.is_some() || self.cancel.is_some()
+            || self.tape.as_ref().is_some_and(|tape| tape.limit().is_some())
+    }
+}
@@ -0,0 +1,45 @@
+use std::{fmt, str::FromStr};
+use crate::error::ParseError;
+
+/// Source languages a program may be written in. Only `Brainfuck` is implemented; the
+/// others are named so that asking for them fails clearly instead of misparsing.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Dialect {
+    #[default]
+    Brainfuck,
+    /// Two-dimensional Befunge-style programs, where `^v<>` steer execution.
+    Funge,
+}
+
+impl Dialect {
+    /// The characters that mean something in the dialect; all others are comments. Funge
+    /// adds `^` and `v` and keeps line breaks, which lay out its rows.
+    pub fn commands(self) -> &'static [char] {
+        match self {
+            Dialect::Brainfuck => &['+', '-', '>', '<', ',', '.', '[', ']'],
+            Dialect::Funge => &['+', '-', '>', '<', ',', '.', '[', ']', '^', 'v', '\n'],
+        }
+    }
+}
+
+impl fmt::Display for Dialect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Dialect::Brainfuck => write!(f, "brainfuck"),
+            Dialect::Funge => write!(f, "funge"),
+        }
+    }
+}
+
+/// Parses the names a `--dialect` flag would take.
+impl FromStr for Dialect {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "brainfuck" | "bf" => Ok(Dialect::Brainfuck),
+            "funge" | "befunge" => Ok(Dialect::Funge),
+            _ => Err(ParseError::UnknownDialect(s.to_string())),
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use std::{error::Error, fmt, io};
+use crate::dialect::Dialect;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExecError {
+    Cancelled,
+    TimedOut,
+    StepLimit {
+        steps: u64,
+    },
+    OutOfBounds {
+        pos: i32,
+    },
+    /// A growable tape would have had to grow past `limit` cells.
+    TapeLimitExceeded {
+        limit: usize,
+    },
+    BadJump {
+        index: i32,
+    },
+    /// The reader returned `WouldBlock` at the `Input` token `index`.
+    NeedsInput {
+        index: usize,
+    },
+    /// Stopped before the output token `index` to let the host look at the state.
+    Paused {
+        index: usize,
+    },
+    /// Reading input or writing output failed.
+    Io {
+        kind: io::ErrorKind,
+    },
+}
+
+impl fmt::Display for ExecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExecError::Cancelled => write!(f, "execution cancelled."),
+            ExecError::TimedOut => write!(f, "execution timed out."),
+            ExecError::StepLimit { steps } => write!(f, "step limit of {} reached.", steps),
+            ExecError::OutOfBounds { pos } => write!(f, "cell {} is outside the tape.", pos),
+            ExecError::TapeLimitExceeded { limit } => write!(f, "tape would grow past its limit of {} cells.", limit),
+            ExecError::BadJump { index } => write!(f, "jump to token {} is outside the program.", index),
+            ExecError::NeedsInput { index } => write!(f, "input at token {} would block.", index),
+            ExecError::Paused { index } => write!(f, "paused before the output at token {}.", index),
+            ExecError::Io { kind } => write!(f, "I/O failed: {}.", kind),
+        }
+    }
+}
+
+impl Error for ExecError {}
+
+impl From<io::Error> for ExecError {
+    fn from(e: io::Error) -> Self {
+        ExecError::Io { kind: e.kind() }
+    }
+}
+
+/// Why a program could not be parsed. Syntax errors carry the same messages `new`
+/// returns.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    Syntax(&'static str),
+    UnsupportedDialect(Dialect),
+    UnknownDialect(String),
+    /// The optimized program printed something other than the source on `input`.
+    Diverged {
+        input: Vec<u8>,
+    },
+    /// More than `limit` loops were open at once.
+    NestingTooDeep {
+        depth: usize,
+        limit: usize,
+    },
+}
+
+impl From<&'static str> for ParseError {
+    fn from(message: &'static str) -> Self {
+        ParseError::Syntax(message)
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Syntax(message) => write!(f, "{}", message),
+            ParseError::UnsupportedDialect(dialect) => write!(f, "the {} dialect is not supported; only brainfuck is.", dialect),
+            ParseError::UnknownDialect(name) => write!(f, "unknown dialect {}.", name),
+            ParseError::Diverged { input } => write!(f, "optimized program diverges from its source on input {:?}.", input),
+            ParseError::NestingTooDeep { depth, limit } => write!(f, "loops nested {} deep; the limit is {}.", depth, limit),
+        }
+    }
+}
+
+impl Error for ParseError {}
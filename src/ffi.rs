@@ -0,0 +1,67 @@
+use std::{
+    ffi::c_void,
+    io::{Read, Write},
+    mem,
+};
+use dynasmrt::{AssemblyOffset, ExecutableBuffer};
+use crate::{
+    config::ExecConfig,
+    interpreter::{Assembled, Interpreter},
+};
+
+/// The JIT entry as seen from C: `void run(uint8_t *cell, void *reader, void *writer)`.
+///
+/// `cell` is where the pointer starts; the program reaches `cell + shift` for every
+/// offset it uses and nothing is bounds checked. `reader` and `writer` are opaque to the
+/// generated code and only passed on to its callbacks, which read them as
+/// `*mut &mut dyn Read` and `*mut &mut dyn Write`, i.e. pointers to Rust trait object
+/// references that must stay valid for the whole call. The writer is not flushed when
+/// the program ends, and a failed read or write ends the program there unreported.
+pub type RawEntry = unsafe extern "C" fn(cell: *mut u8, reader: *mut c_void, writer: *mut c_void);
+
+/// JIT-compiled code without the `Compiled` closure around it, for FFI or tables of
+/// function pointers. The entry is only valid while this is alive.
+pub struct RawCompiled {
+    buf: ExecutableBuffer,
+    entry: AssemblyOffset,
+}
+
+impl RawCompiled {
+    pub fn entry(&self) -> RawEntry {
+        // SAFETY: `c_entry` starts a function following the platform C convention with
+        // the signature of `RawEntry`; see `Interpreter::assemble`.
+        unsafe { mem::transmute(self.buf.ptr(self.entry)) }
+    }
+
+    pub fn buffer(&self) -> &ExecutableBuffer {
+        &self.buf
+    }
+
+    /// Calls the entry on `cells` from index `pointer`, the way a `Compiled` closure does.
+    ///
+    /// # Safety
+    ///
+    /// The program must stay inside `cells` when started at `pointer`.
+    pub unsafe fn call(&self, cells: &mut [u8], pointer: usize, mut reader: &mut dyn Read, mut writer: &mut dyn Write) {
+        let reader: *mut &mut dyn Read = &mut reader;
+        let writer: *mut &mut dyn Write = &mut writer;
+        (self.entry())(cells[pointer..].as_mut_ptr(), reader as *mut c_void, writer as *mut c_void);
+    }
+}
+
+impl Interpreter {
+    /// Compiles under the interpreter's settings like `compile`, except for the tape,
+    /// which the caller passes in, and bounds checks, which are left out. See `RawEntry`
+    /// for the calling convention.
+    pub fn compile_raw(&self) -> RawCompiled {
+        let config = ExecConfig {
+            bounds_check: false,
+            ..self.config().clone()
+        };
+        let Assembled { buf, c_entry, .. } = self.on_any_tape().assemble(&config);
+        RawCompiled {
+            buf,
+            entry: c_entry,
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use crate::{
+    dialect::Dialect,
+    error::ParseError,
+    interpreter::Interpreter,
+};
+
+/// The commands of `src` in `dialect` and nothing else; for `Dialect::Brainfuck`, the
+/// eight that `new` sees. `!` and `#`, which some interpreters take as an input separator
+/// and a debug dump, are comments too, since no dialect here has them.
+pub fn strip_comments(src: &str, dialect: Dialect) -> String {
+    src.chars().filter(|c| dialect.commands().contains(c)).collect()
+}
+
+/// Translates Ook!-style source, where every command is a pair of `word` tokens each
+/// ending in `.`, `?` or `!`, into Brainfuck. Anything between tokens is ignored.
+pub fn pairs_to_bf(src: &str, word: &str) -> Result<String, &'static str> {
+    let mut marks = Vec::new();
+    let mut rest = src;
+    while let Some(at) = rest.find(word) {
+        rest = &rest[at + word.len()..];
+        if let Some(mark @ ('.' | '?' | '!')) = rest.chars().next() {
+            marks.push(mark);
+            rest = &rest[1..];
+        }
+    }
+    if marks.len() % 2 != 0 {
+        return Err("unpaired word.");
+    }
+    marks.chunks(2).map(|pair| match (pair[0], pair[1]) {
+        ('.', '?') => Ok('>'),
+        ('?', '.') => Ok('<'),
+        ('.', '.') => Ok('+'),
+        ('!', '!') => Ok('-'),
+        ('!', '.') => Ok('.'),
+        ('.', '!') => Ok(','),
+        ('!', '?') => Ok('['),
+        ('?', '!') => Ok(']'),
+        _ => Err("word pair is not a command."),
+    }).collect()
+}
+
+/// Translates Spoon, which spells each command as a prefix-free binary code. Characters
+/// other than `0` and `1` are ignored.
+pub fn spoon_to_bf(src: &str) -> Result<String, &'static str> {
+    const CODES: [(&str, char); 8] = [
+        ("1", '+'),
+        ("000", '-'),
+        ("010", '>'),
+        ("011", '<'),
+        ("0011", ']'),
+        ("00100", '['),
+        ("001010", '.'),
+        ("0010110", ','),
+    ];
+    let mut bf = String::new();
+    let mut code = String::new();
+    for bit in src.chars().filter(|c| *c == '0' || *c == '1') {
+        code.push(bit);
+        if let Some(&(_, command)) = CODES.iter().find(|(c, _)| *c == code) {
+            bf.push(command);
+            code.clear();
+        } else if code.len() >= 7 {
+            return Err("invalid spoon code.");
+        }
+    }
+    if !code.is_empty() {
+        return Err("incomplete spoon code.");
+    }
+    Ok(bf)
+}
+
+impl Interpreter {
+    pub fn from_ook(src: &str) -> Result<Self, ParseError> {
+        Ok(Self::new(pairs_to_bf(src, "Ook")?.chars())?)
+    }
+
+    /// Blub is Ook! with `Blub` in place of `Ook`.
+    pub fn from_blub(src: &str) -> Result<Self, ParseError> {
+        Ok(Self::new(pairs_to_bf(src, "Blub")?.chars())?)
+    }
+
+    pub fn from_spoon(src: &str) -> Result<Self, ParseError> {
+        Ok(Self::new(spoon_to_bf(src)?.chars())?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HELLO: &str = "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.";
+
+    /// `bf` spelled in Ook!-style pairs of `word`, one command per line.
+    fn to_pairs(bf: &str, word: &str) -> String {
+        bf.chars().map(|c| {
+            let (a, b) = match c {
+                '>' => ('.', '?'),
+                '<' => ('?', '.'),
+                '+' => ('.', '.'),
+                '-' => ('!', '!'),
+                '.' => ('!', '.'),
+                ',' => ('.', '!'),
+                '[' => ('!', '?'),
+                ']' => ('?', '!'),
+                _ => unreachable!(),
+            };
+            format!("{w}{} {w}{}\n", a, b, w = word)
+        }).collect()
+    }
+
+    #[test]
+    fn hello_world_in_ook() {
+        let ook = to_pairs(HELLO, "Ook");
+        assert!(ook.starts_with("Ook. Ook.\n"));
+        let program = Interpreter::from_ook(&ook).unwrap();
+        assert_eq!(program.run_bytes(b"").unwrap(), b"Hello World!\n");
+    }
+
+    #[test]
+    fn hello_world_in_blub() {
+        let program = Interpreter::from_blub(&to_pairs(HELLO, "Blub")).unwrap();
+        assert_eq!(program.run_bytes(b"").unwrap(), b"Hello World!\n");
+    }
+
+    #[test]
+    fn hello_world_in_spoon() {
+        let spoon: String = HELLO.chars().map(|c| match c {
+            '+' => "1",
+            '-' => "000",
+            '>' => "010",
+            '<' => "011",
+            ']' => "0011",
+            '[' => "00100",
+            '.' => "001010",
+            _ => unreachable!(),
+        }).collect();
+        assert_eq!(spoon_to_bf(&spoon).unwrap(), HELLO);
+        let program = Interpreter::from_spoon(&spoon).unwrap();
+        assert_eq!(program.run_bytes(b"").unwrap(), b"Hello World!\n");
+    }
+
+    #[test]
+    fn malformed_sources_are_rejected() {
+        assert_eq!(pairs_to_bf("Ook. Ook. Ook.", "Ook"), Err("unpaired word."));
+        assert_eq!(pairs_to_bf("Ook? Ook?", "Ook"), Err("word pair is not a command."));
+        assert_eq!(spoon_to_bf("00"), Err("incomplete spoon code."));
+        assert_eq!(spoon_to_bf("0010111"), Err("invalid spoon code."));
+        assert!(Interpreter::from_ook("Ook! Ook?").is_err());
+    }
+
+    #[test]
+    fn comments_are_stripped() {
+        assert_eq!(strip_comments("a+b-#!c[>].", Dialect::Brainfuck), "+-[>].");
+    }
+
+    #[test]
+    fn comments_are_stripped_per_dialect() {
+        let src = "+v\n#^ x<";
+        assert_eq!(strip_comments(src, Dialect::Brainfuck), "+<");
+        assert_eq!(strip_comments(src, Dialect::Funge), "+v\n^<");
+    }
+}
\ No newline at end of file
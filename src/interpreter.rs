@@ -1,325 +1,2265 @@
-use std::{
-    mem,
-    io::{Read, Write},
-};
-use dynasmrt::{dynasm, DynasmApi, DynasmLabelApi, x64::Assembler};
-use std::collections::HashMap;
-
-#[derive(Debug, Copy, Clone)]
-enum Token {
-    Add(i16, i32),
-    Mul(i16, i32, i32),
-    AddTo(i32, i32),
-    Clear(i32),
-    Shift(i32),
-    LoopBegin(i32),
-    LoopEnd(i32),
-    Input(i32),
-    Output(i32),
-    End,
-}
-
-#[derive(Debug)]
-pub struct Interpreter {
-    inst: Vec<Token>,
-}
-
-impl Interpreter {
-    pub fn new<I: IntoIterator<Item=char>>(stream: I) -> Result<Self, &'static str> {
-        let mut inst = Vec::new();
-        let mut depth = 0;
-        let mut shift = 0;
-        let mut begin = 0;
-        let mut mp = HashMap::new();
-        for c in stream.into_iter() {
-            match match c {
-                '+' => Token::Add(1, 0),
-                '-' => Token::Add(-1, 0),
-                '>' => Token::Shift(1),
-                '<' => Token::Shift(-1),
-                ',' => Token::Input(0),
-                '.' => Token::Output(0),
-                '[' => {
-                    depth += 1;
-                    Token::LoopBegin(0)
-                }
-                ']' => {
-                    depth -= 1;
-                    if depth < 0 {
-                        return Err("[ missing.");
-                    }
-                    Token::LoopEnd(0)
-                }
-                _ => continue,
-            } {
-                Token::Add(n, _) => {
-                    match mp.get_mut(&shift) {
-                        None => { mp.insert(shift, n); }
-                        Some(add) => { *add += n; }
-                    }
-                }
-                Token::Shift(n) => {
-                    shift += n;
-                }
-                Token::Output(_) => {
-                    if let Some(add) = mp.get(&shift) {
-                        inst.push(Token::Add(*add, shift));
-                        mp.remove(&shift);
-                    }
-                    inst.push(Token::Output(shift));
-                }
-                Token::Input(_) => {
-                    if let Some(_) = mp.get(&shift) {
-                        mp.remove(&shift);
-                    }
-                    inst.push(Token::Input(shift));
-                }
-                Token::LoopBegin(_) => {
-                    for (shift, add) in &mp {
-                        if *add != 0 {
-                            inst.push(Token::Add(*add, *shift));
-                        }
-                    }
-                    mp.clear();
-                    if shift != 0 {
-                        inst.push(Token::Shift(shift));
-                        shift = 0;
-                    }
-                    inst.push(Token::LoopBegin(0));
-                    begin = inst.len();
-                }
-                Token::LoopEnd(_) => {
-                    if inst.len() == begin && shift == 0 && &mp.get(&0) == &Some(&-1) {
-                        inst.pop().unwrap();
-                        if let Some(Token::Shift(prev_shift)) = inst.last() {
-                            shift = *prev_shift;
-                            inst.pop();
-                        }
-                        mp.remove(&0);
-                        for (offset, add) in &mp {
-                            inst.push(match *add {
-                                0 => continue,
-                                1 => Token::AddTo(*offset + shift, shift),
-                                _ => Token::Mul(*add, *offset + shift, shift),
-                            });
-                        }
-                        inst.push(Token::Clear(shift));
-                        mp.clear();
-                        begin = 0;
-                    } else {
-                        for (shift, add) in &mp {
-                            if *add != 0 {
-                                inst.push(Token::Add(*add, *shift));
-                            }
-                        }
-                        if shift != 0 {
-                            inst.push(Token::Shift(shift));
-                            shift = 0;
-                        }
-                        inst.push(Token::LoopEnd(0));
-                        mp.clear();
-                    }
-                }
-                _ => {}
-            }
-        }
-        if depth > 0 {
-            return Err("] missing.");
-        }
-        inst.push(Token::End);
-        Ok(Self {
-            inst
-        }.build_jump_addr())
-    }
-
-    fn build_jump_addr(self) -> Self {
-        let mut opt = Vec::new();
-        let mut stack = Vec::new();
-        for i in 0..self.inst.len() {
-            match self.inst[i] {
-                Token::LoopBegin(_) => {
-                    stack.push(i);
-                    opt.push(Token::LoopBegin(0));
-                }
-                Token::LoopEnd(_) => {
-                    let pos = stack.pop().unwrap();
-                    let shift = i as i32 - pos as i32;
-                    opt[pos] = Token::LoopBegin(shift + 1);
-                    opt.push(Token::LoopEnd(1 - shift));
-                }
-                tk => opt.push(tk),
-            }
-        }
-        Self {
-            inst: opt,
-        }
-    }
-
-    #[allow(dead_code)]
-    pub fn run(&self, reader: &mut dyn Read, writer: &mut dyn Write) {
-        let mut i = 0;
-        let mut pos = 0;
-        let mut buffer = [0u8; 0xffff];
-        loop {
-            match self.inst[i as usize] {
-                Token::Add(n, shift) => {
-                    let rhs = buffer[(pos + shift) as usize] as i16;
-                    buffer[(pos + shift) as usize] = (n + rhs) as u8;
-                }
-                Token::Mul(n, shift, base) => {
-                    let rhs = buffer[(pos + shift) as usize] as i16;
-                    let mul = buffer[(pos + base) as usize] as i16;
-                    buffer[(pos + shift) as usize] = (n * mul + rhs) as u8;
-                }
-                Token::AddTo(to, from) => {
-                    let to_n = buffer[(to + pos) as usize] as i16;
-                    let from_n = buffer[(pos + from) as usize] as i16;
-                    buffer[(to + pos) as usize] = (from_n + to_n) as u8;
-                }
-                Token::Clear(shift) => buffer[(pos + shift) as usize] = 0,
-                Token::Shift(shift) => pos += shift,
-                Token::LoopBegin(label) => if buffer[pos as usize] == 0 {
-                    i += label;
-                    continue;
-                }
-                Token::LoopEnd(label) => if buffer[pos as usize] != 0 {
-                    i += label;
-                    continue;
-                }
-                Token::Input(shift) => {
-                    let mut buf = [0u8];
-                    reader.read(&mut buf).unwrap();
-                    buffer[(pos + shift) as usize] = buf[0];
-                }
-                Token::Output(shift) => {
-                    writer.write(&[buffer[(pos + shift) as usize]]).unwrap();
-                }
-                Token::End => break,
-            }
-            i += 1;
-        }
-    }
-
-    #[allow(dead_code)]
-    pub fn compile(&self) -> Box<dyn Fn(&dyn Read, &dyn Write)> {
-        let mut ops = Assembler::new().unwrap();
-        let start = ops.offset();
-        let mut labels = Vec::new();
-        dynasm!(ops
-            ; push rbp
-            ; mov rbp, rsp
-            ; sub rsp, 0x30
-            ; mov [rsp + 0x18], rdx
-            ; mov [rsp + 0x10], r8
-            ; mov rdx, 0
-        );
-        for i in 0..self.inst.len() {
-            match self.inst[i] {
-                Token::Add(n, shift) => {
-                    dynasm!(ops
-                        ; add BYTE [rcx + rdx + shift as _], n as _
-                    );
-                }
-                Token::Mul(n, shift, base) => {
-                    dynasm!(ops
-                        ; mov al, BYTE [rcx + rdx + base as _]
-                        ; mov r8b, n as _
-                        ; mul r8b
-                        ; add BYTE [rcx + rdx + shift as _], al
-                    );
-                }
-                Token::AddTo(to, from) => {
-                    dynasm!(ops
-                        ; mov al, BYTE [rcx + rdx + from as _]
-                        ; add BYTE [rcx + rdx + to as _], al
-                    );
-                }
-                Token::Clear(shift) => {
-                    dynasm!(ops
-                        ; mov BYTE [rcx + rdx + shift as _], 0
-                    );
-                }
-                Token::Shift(shift) => {
-                    dynasm!(ops
-                        ; add rdx, shift as _
-                    );
-                }
-                Token::LoopBegin(_) => {
-                    let backward_label = ops.new_dynamic_label();
-                    let forward_label = ops.new_dynamic_label();
-                    labels.push((backward_label, forward_label));
-                    dynasm!(ops
-                        ; cmp BYTE [rcx + rdx], 0
-                        ; jz =>forward_label
-                        ;=>backward_label
-                    );
-                }
-                Token::LoopEnd(_) => {
-                    let (backward_label, forward_label) = labels.pop().unwrap();
-                    dynasm!(ops
-                        ; cmp BYTE [rcx + rdx], 0
-                        ; jnz =>backward_label
-                        ;=>forward_label
-                    );
-                }
-                Token::Input(shift) => {
-                    dynasm!(ops
-                        ; mov [rsp + 0x28], rcx
-                        ; mov [rsp + 0x20], rdx
-                        ; mov rcx, [rsp + 0x18]
-                        ; mov rdx, [rsp + 0x10]
-                        ; mov rax, QWORD Self::getchar as _
-                        ; call rax
-                        ; mov rdx, [rsp + 0x20]
-                        ; mov rcx, [rsp + 0x28]
-                        ; mov [rcx + rdx + shift as _], rax
-                    );
-                }
-                Token::Output(shift) => {
-                    dynasm!(ops
-                        ; mov [rsp + 0x28], rcx
-                        ; mov [rsp + 0x20], rdx
-                        ; mov cl, [rcx + rdx + shift as _]
-                        ; mov rdx, [rsp + 0x10]
-                        ; mov rax, QWORD Self::putchar as _
-                        ; call rax
-                        ; mov rdx, [rsp + 0x20]
-                        ; mov rcx, [rsp + 0x28]
-                    );
-                }
-                Token::End => {
-                    dynasm!(ops
-                        ; mov rsp, rbp
-                        ; pop rbp
-                        ; ret
-                    );
-                }
-            }
-        }
-        let buf = ops.finalize().unwrap();
-        Box::new(move |reader: &dyn Read, writer: &dyn Write| {
-            let mut buffer = [0u8; 0xffff];
-            let f: fn(_, _, _) = unsafe { mem::transmute(buf.ptr(start)) };
-            let raw_reader = Box::into_raw(Box::new(reader));
-            let raw_writer = Box::into_raw(Box::new(writer));
-            f(buffer.as_mut_ptr(), raw_reader, raw_writer);
-            unsafe {
-                Box::from_raw(raw_reader);
-                Box::from_raw(raw_writer);
-            }
-        })
-    }
-
-    unsafe fn putchar(char: u8, writer: *mut &mut dyn Write) {
-        let buf = [char as u8];
-        let writer = &mut **writer;
-        writer.write(&buf).unwrap();
-        writer.flush().unwrap();
-    }
-
-    unsafe fn getchar(reader: *mut &mut dyn Read) -> u8 {
-        let mut buf = [0];
-        (**reader).read(&mut buf).unwrap();
-        buf[0]
-    }
+use std::{
+    borrow::Cow,
+    cell::UnsafeCell,
+    fmt,
+    fs::File,
+    mem,
+    io::{self, Read, Write},
+    ops::Deref,
+    path::Path,
+    time::{Duration, Instant},
+};
+use dynasmrt::{dynasm, AssemblyOffset, DynasmApi, DynasmLabelApi, ExecutableBuffer, x64::Assembler};
+use memmap2::Mmap;
+use std::collections::{HashMap, HashSet};
+use crate::{
+    cancel::CancelHandle,
+    config::{CellArith, ExecConfig},
+    dialect::Dialect,
+    error::{ExecError, ParseError},
+    io::{code_kind, flush_out, kind_code, write_out, CountingWriter, DebugWriter, EofPolicy, FlushPolicy, SliceInput, VecOutput},
+    locality::PointerTrace,
+    parser::{OptReport, Parser},
+    tape::{Tape, TAPE_SIZE},
+    threaded::Threaded,
+    watch::{Access, Watch},
+};
+
+const CANCEL_CHECK_INTERVAL: u32 = 0x1000;
+const ASSUMED_LOOP_ITERATIONS: u64 = 16;
+
+/// `execute` JIT-compiles a program once it is expected to run each token at least this
+/// many times on average; below that, assembling the code costs more than it saves.
+pub const DEFAULT_JIT_THRESHOLD: u64 = 32;
+
+/// Longest program `execute` JIT-compiles. The code takes around 15 bytes per token and
+/// is staged in memory before being copied into its executable buffer, so a program at
+/// this limit peaks near 200MB while assembling; longer ones are interpreted.
+pub const MAX_JIT_TOKENS: usize = 1 << 22;
+
+/// Most tokens `new` reserves room for up front, about 24MB; longer programs grow the
+/// token vector as they are parsed.
+const MAX_RESERVED_TOKENS: usize = 1 << 20;
+
+/// Set in the result of a JIT I/O callback, and of the code, when a read or write failed.
+const IO_FAILED: u64 = 1 << 33;
+
+pub type Compiled = Box<dyn Fn(&mut dyn Read, &mut dyn Write)>;
+
+pub type CheckedCompiled = Box<dyn Fn(&mut dyn Read, &mut dyn Write) -> Result<(), ExecError>>;
+
+pub type InstrumentedCompiled = Box<dyn Fn(&mut dyn Read, &mut dyn Write) -> JitStats>;
+
+/// What one run of `compile_instrumented` code counted. `steps` counts tokens as the
+/// interpreter's step limit does, `iterations` the times any loop body was entered,
+/// and `inputs` and `outputs` bytes.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct JitStats {
+    pub steps: u64,
+    pub iterations: u64,
+    pub inputs: u64,
+    pub outputs: u64,
+}
+
+/// What `compile` returns: the closure, which it derefs to so it can be called as
+/// `program(reader, writer)`, and facts about the code behind it.
+pub struct CompiledProgram {
+    run: Compiled,
+    /// Machine code size in bytes.
+    pub code_size: usize,
+    /// Loops left in the program after optimization.
+    pub loops: usize,
+    /// Time spent assembling.
+    pub assembly: Duration,
+}
+
+impl Deref for CompiledProgram {
+    type Target = Compiled;
+
+    fn deref(&self) -> &Compiled {
+        &self.run
+    }
+}
+
+impl fmt::Debug for CompiledProgram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CompiledProgram")
+            .field("code_size", &self.code_size)
+            .field("loops", &self.loops)
+            .field("assembly", &self.assembly)
+            .finish()
+    }
+}
+
+/// The output of `Interpreter::assemble`. The buffer holds everything the code refers
+/// to, `OutputBytes` literals included.
+pub(crate) struct Assembled {
+    pub buf: ExecutableBuffer,
+    /// Where win64 callers enter.
+    pub entry: AssemblyOffset,
+    /// Where callers using the platform's C convention enter.
+    pub c_entry: AssemblyOffset,
+    pub eof: EofPolicy,
+}
+
+impl Assembled {
+    /// Runs the code on a copy of `tape`; only code assembled with `bounds_check` fails.
+    fn run(&self, tape: &Tape, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<(), ExecError> {
+        let mut copy = tape.clone();
+        // SAFETY: `entry` is the win64 entry `assemble` put in `buf`, and checked code was
+        // assembled for the length of `tape`, which `copy` shares.
+        self.eof.with_reader(reader, |reader| unsafe { execute_buffer(&self.buf, self.entry, &mut copy, reader, writer) })
+    }
+}
+
+/// Runs JIT code from `Interpreter::compile_buffer` on `tape` in place, starting at its
+/// pointer, and flushes the writer after. The pointer is left where the program stopped,
+/// or on cell 0 for code compiled with `ExecConfig::reset_pointer`. The entry follows the win64 convention and takes
+/// `(cell, reader, writer, tape_base) -> u64`: the reader and writer are
+/// `*mut &mut dyn Read` and `*mut &mut dyn Write` handed through to the callbacks. The
+/// low half of the result is the cell the pointer ended on, or with bit 32 set, the cell
+/// of an out-of-bounds access, or with bit 33 set, the `io::ErrorKind` numbered by
+/// `io::kind_code` of a failed read or write.
+/// Under `EofPolicy::InfiniteZeros` the reader is not fused here; pass an `io::Fused`.
+///
+/// # Safety
+///
+/// `entry` must be the entry of `buf` as returned by `compile_buffer`. Code assembled
+/// without `bounds_check` must not leave `tape`; checked code must be run on a tape of
+/// the length it was compiled for.
+pub unsafe fn execute_buffer(buf: &ExecutableBuffer, entry: AssemblyOffset, tape: &mut Tape, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<(), ExecError> {
+    let pointer = call_buffer(buf, entry, tape, reader, writer)?;
+    tape.set_pointer(pointer);
+    Ok(())
+}
+
+/// `execute_buffer`, returning the cell the pointer ended on.
+pub(crate) unsafe fn call_buffer(buf: &ExecutableBuffer, entry: AssemblyOffset, tape: &mut Tape, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<usize, ExecError> {
+    let pointer = tape.pointer();
+    let cells = tape.cells_mut();
+    let base = cells.as_mut_ptr();
+    let cell = cells[pointer..].as_mut_ptr();
+    let raw_reader = Box::into_raw(Box::new(reader));
+    let raw_writer = Box::into_raw(Box::new(writer));
+    // SAFETY: per the contract above, `entry` starts code from `assemble`: the cell
+    // pointer, reader, writer and tape base arrive in rcx, rdx, r8 and r9, the
+    // callee-saved registers it uses are restored before `ret`, and rsp stays 16-byte
+    // aligned with 32 bytes of shadow space around every call. The callbacks are
+    // `extern "win64"` and take the boxed `&mut dyn Read`/`Write` pointers passed here,
+    // which stay alive until the call returns and are freed exactly once below. Cell
+    // accesses are single bytes at `[rbx + shift]`, inside `tape` by the caller's promise
+    // or by the checks.
+    let (status, writer) = {
+        let f: extern "win64" fn(*mut u8, *mut &mut dyn Read, *mut &mut dyn Write, *mut u8) -> u64 =
+            mem::transmute(buf.ptr(entry));
+        let status = f(cell, raw_reader, raw_writer, base);
+        drop(Box::from_raw(raw_reader));
+        (status, Box::from_raw(raw_writer))
+    };
+    flush_out(&mut **writer).unwrap();
+    match status >> 32 {
+        0 => Ok(status as usize),
+        1 => Err(ExecError::OutOfBounds { pos: status as u32 as i32 }),
+        _ => Err(ExecError::Io { kind: code_kind(status as u32) }),
+    }
+}
+
+/// JIT code in a buffer the caller owns, e.g. to keep many programs' code in a slab;
+/// a safe handle over `execute_buffer`.
+pub struct ProgramBuffer {
+    buf: ExecutableBuffer,
+    entry: AssemblyOffset,
+    // The tape length checked code was assembled for.
+    checked_len: Option<usize>,
+    eof: EofPolicy,
+}
+
+impl ProgramBuffer {
+    pub fn buffer(&self) -> &ExecutableBuffer {
+        &self.buf
+    }
+
+    pub fn entry(&self) -> AssemblyOffset {
+        self.entry
+    }
+
+    /// The buffer and its win64 entry, for `execute_buffer`.
+    pub fn into_parts(self) -> (ExecutableBuffer, AssemblyOffset) {
+        (self.buf, self.entry)
+    }
+
+    /// Runs the code on `tape` in place; see `execute_buffer`. Like a `compile` closure,
+    /// code assembled without `bounds_check` trusts the program to stay on the tape.
+    pub fn execute(&self, tape: &mut Tape, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<(), ExecError> {
+        if let Some(len) = self.checked_len {
+            assert_eq!(tape.cells().len(), len, "tape length differs from the compiled one.");
+        }
+        // SAFETY: `entry` belongs to `buf` and the tape length was checked above.
+        self.eof.with_reader(reader, |reader| unsafe { execute_buffer(&self.buf, self.entry, tape, reader, writer) })
+    }
+}
+
+
+/// What `interpret` measured on the way; steps are only counted when asked for or
+/// when a step limit is set. Any `watch` is told about accesses to its cells, and any
+/// `pointers` trace records every access. Execution
+/// begins at token `start`, e.g. the `Input` an earlier run stopped at. With
+/// `pause_output`, the run stops before every output except one it starts on when
+/// `resume_output` is set. With a `budget`, the run stops once it has taken that many
+/// steps, setting `yielded` to the token to go on from.
+#[derive(Default)]
+pub(crate) struct Meter<'a> {
+    pub start: usize,
+    pub pause_output: bool,
+    pub resume_output: bool,
+    pub budget: Option<u64>,
+    pub yielded: Option<usize>,
+    pub count_steps: bool,
+    pub steps: u64,
+    pub pointer: i32,
+    pub watch: Option<Watch<'a>>,
+    pub pointers: Option<PointerTrace>,
+}
+
+/// How long `execute` spent assembling and running a program. `compile` is `None`
+/// when the program was interpreted.
+#[derive(Debug, Copy, Clone)]
+pub struct Timings {
+    pub compile: Option<Duration>,
+    pub run: Duration,
+}
+
+/// One optimized instruction. Every `i32` offset is relative to the pointer, which only
+/// `Shift` moves; cell arithmetic wraps modulo 256.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Token {
+    /// `Add(n, shift)`: adds `n` to the cell at `shift`.
+    Add(i16, i32),
+    /// `Mul(n, shift, base)`: adds `n` times the cell at `base` to the cell at `shift`.
+    /// Like the loop it was collapsed from, it does not touch `shift` while `base` is
+    /// zero, so the target may then lie off the tape.
+    Mul(i16, i32, i32),
+    /// `AddTo(to, from)`: adds the cell at `from` to the cell at `to`, leaving `to`
+    /// alone while `from` is zero, as `Mul` does.
+    AddTo(i32, i32),
+    /// `Clear(shift)`: zeroes the cell at `shift`.
+    Clear(i32),
+    /// `Set(n, shift)`: stores `n` in the cell at `shift`.
+    Set(u8, i32),
+    /// `Shift(n)`: moves the pointer by `n`.
+    Shift(i32),
+    /// When the current cell is zero, jumps forward by the operand to the token just past
+    /// the matching `LoopEnd`.
+    LoopBegin(i32),
+    /// When the current cell is non-zero, jumps back by the (negative) operand to the
+    /// first token of the loop body.
+    LoopEnd(i32),
+    /// `Scan(n)`: moves the pointer by `n` until it is on a zero cell, like `[>]` for
+    /// `n == 1`.
+    Scan(i32),
+    /// `Input(shift)`: reads one byte into the cell at `shift`.
+    Input(i32),
+    /// `Output(shift)`: writes the cell at `shift`.
+    Output(i32),
+    /// Writes the bytes in one go; stands in for a run of outputs whose values were known
+    /// when the program was optimized.
+    OutputBytes(Vec<u8>),
+    /// Does nothing. Passes leave it in place of a token they remove, and `compact`
+    /// drops it before loops are linked, so a built program never holds one.
+    Nop,
+    /// Stops the program; always the last token.
+    End,
+}
+
+/// Checked constructors for building token streams by hand. Loops are created unlinked,
+/// since `Interpreter::from_tokens` recomputes their offsets.
+impl Token {
+    pub fn add(n: i16, shift: i32) -> Result<Self, &'static str> {
+        if n == 0 {
+            return Err("add of zero.");
+        }
+        Ok(Token::Add(n, shift))
+    }
+
+    pub fn mul(n: i16, shift: i32, base: i32) -> Result<Self, &'static str> {
+        if n == 0 {
+            return Err("multiply by zero.");
+        }
+        if shift == base {
+            return Err("multiply into its own base cell.");
+        }
+        Ok(Token::Mul(n, shift, base))
+    }
+
+    pub fn add_to(to: i32, from: i32) -> Result<Self, &'static str> {
+        if to == from {
+            return Err("add of a cell to itself.");
+        }
+        Ok(Token::AddTo(to, from))
+    }
+
+    pub fn shift(n: i32) -> Result<Self, &'static str> {
+        if n == 0 {
+            return Err("shift by zero.");
+        }
+        Ok(Token::Shift(n))
+    }
+
+    pub fn scan(n: i32) -> Result<Self, &'static str> {
+        if n == 0 {
+            return Err("scan by zero.");
+        }
+        Ok(Token::Scan(n))
+    }
+
+    pub fn loop_begin() -> Self {
+        Token::LoopBegin(0)
+    }
+
+    pub fn loop_end() -> Self {
+        Token::LoopEnd(0)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Interpreter {
+    inst: Vec<Token>,
+    // The program optimized without assuming the tape starts blank, when that differs
+    // from `inst`; see `on_any_tape`.
+    seeded: Option<Vec<Token>>,
+    config: ExecConfig,
+}
+
+/// Serialized as the bare optimized token stream, in the version that holds on any tape
+/// like `encode`; loop offsets are relinked through `from_tokens` on load.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Interpreter {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.any_tape_tokens().serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Interpreter {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let inst = Vec::<Token>::deserialize(deserializer)?;
+        Self::from_tokens(inst).map_err(serde::de::Error::custom)
+    }
+}
+
+impl Interpreter {
+    pub fn new<I: IntoIterator<Item=char>>(stream: I) -> Result<Self, &'static str> {
+        Self::new_with_report(stream).map(|(interpreter, _)| interpreter)
+    }
+
+    /// Like `new`, reserving room for `hint` tokens up front so a large program is not
+    /// reallocated as it is parsed. The source length is an upper bound, and usually far
+    /// above the count since comments and fused runs make no tokens; `new` reserves
+    /// `estimated_tokens` of the stream's lower size hint.
+    pub fn with_capacity<I: IntoIterator<Item=char>>(stream: I, hint: usize) -> Result<Self, &'static str> {
+        Self::parse(stream, hint).map(|(interpreter, _)| interpreter)
+    }
+
+    /// Like `new`, also counting which optimizations fired and flagging cells that a
+    /// block of `+` or `-` wraps; see `Wrap`.
+    pub fn new_with_report<I: IntoIterator<Item=char>>(stream: I) -> Result<(Self, OptReport), &'static str> {
+        let stream = stream.into_iter();
+        let hint = Self::estimated_tokens(stream.size_hint().0);
+        Self::parse(stream, hint)
+    }
+
+    /// A guess at the token count of `len` characters of source: a quarter of them, up
+    /// to `MAX_RESERVED_TOKENS`.
+    fn estimated_tokens(len: usize) -> usize {
+        (len / 4).min(MAX_RESERVED_TOKENS)
+    }
+
+    fn parse<I: IntoIterator<Item=char>>(stream: I, hint: usize) -> Result<(Self, OptReport), &'static str> {
+        #[cfg(feature = "verify")]
+        let source: String = stream.into_iter().collect();
+        #[cfg(feature = "verify")]
+        let stream = source.chars();
+        let mut parser = Parser::with_capacity(hint);
+        parser.feed(stream)?;
+        let interpreter = parser.finish()?;
+        #[cfg(feature = "verify")]
+        if let Err(e) = interpreter.verify(&source) {
+            panic!("{}", e);
+        }
+        let mut report = parser.report();
+        let collapsed = report.clear_loops + report.copy_loops + report.multiply_loops;
+        let mut kept = 0;
+        for token in &interpreter.inst {
+            match token {
+                Token::Clear(_) | Token::Set(..) => kept += 1,
+                Token::Scan(_) => report.scan_loops += 1,
+                _ => {}
+            }
+        }
+        report.dead_loops = collapsed - kept;
+        Ok((interpreter, report))
+    }
+
+    /// Like `new`, but fails with `ParseError::NestingTooDeep` as soon as more than
+    /// `limit` loops are open, without reading the rest of `stream`. `new` itself has no
+    /// limit.
+    pub fn with_max_depth<I: IntoIterator<Item=char>>(stream: I, limit: usize) -> Result<Self, ParseError> {
+        let mut depth = 0;
+        let mut too_deep = false;
+        let stream = stream.into_iter().take_while(|&c| {
+            match c {
+                '[' => depth += 1,
+                ']' => depth -= 1,
+                _ => {}
+            }
+            too_deep = depth > limit as i64;
+            !too_deep
+        });
+        let parsed = Self::new(stream);
+        if too_deep {
+            return Err(ParseError::NestingTooDeep { depth: limit + 1, limit });
+        }
+        Ok(parsed?)
+    }
+
+    /// Parses `stream` as `dialect`; anything but `Dialect::Brainfuck` is rejected with
+    /// `ParseError::UnsupportedDialect`.
+    pub fn with_dialect<I: IntoIterator<Item=char>>(stream: I, dialect: Dialect) -> Result<Self, ParseError> {
+        match dialect {
+            Dialect::Brainfuck => Ok(Self::new(stream)?),
+            _ => Err(ParseError::UnsupportedDialect(dialect)),
+        }
+    }
+
+    /// Parses source given as bytes, in any encoding: only the eight ASCII commands
+    /// mean anything, so nothing is decoded and every other byte is a comment.
+    pub fn from_bytes(source: &[u8]) -> Result<Self, &'static str> {
+        Self::new(source.iter().map(|&b| b as char))
+    }
+
+    /// Parses a source file through a memory map instead of reading it into a `String`.
+    /// Commands are ASCII, so bytes are scanned directly and never UTF-8 validated.
+    /// Parse errors are reported as `io::ErrorKind::InvalidData`.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let parsed = if file.metadata()?.len() == 0 {
+            Self::new(None)
+        } else {
+            let map = unsafe { Mmap::map(&file)? };
+            Self::from_bytes(&map)
+        };
+        parsed.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Builds an interpreter from an already optimized token stream. Loop offsets are
+    /// recomputed, so `LoopBegin`/`LoopEnd` operands may be left as zero.
+    pub fn from_tokens(mut inst: Vec<Token>) -> Result<Self, &'static str> {
+        if !matches!(inst.last(), Some(Token::End)) {
+            inst.push(Token::End);
+        }
+        let seeded = Self::optimize(inst.clone(), false);
+        Self::from_optimized(Self::optimize(inst, true), seeded)
+    }
+
+    /// Builds an interpreter from the tokens already through the passes for a blank
+    /// tape and for any tape.
+    pub(crate) fn from_optimized(inst: Vec<Token>, seeded: Vec<Token>) -> Result<Self, &'static str> {
+        Self {
+            seeded: if seeded != inst { Some(seeded) } else { None },
+            inst,
+            config: ExecConfig::default(),
+        }.build_jump_addr()
+    }
+
+    /// Tokens taken as they are, with loop operands already linked.
+    pub(crate) fn with_tokens(inst: Vec<Token>, config: ExecConfig) -> Self {
+        Self { inst, seeded: None, config }
+    }
+
+    /// The program as optimized for a tape that may not start blank, such as a seeded
+    /// one or a `Machine`'s. Only the straight-line code before the first loop differs.
+    pub(crate) fn on_any_tape(&self) -> Cow<'_, Self> {
+        match self.seeded {
+            Some(ref seeded) => Cow::Owned(Self::with_tokens(seeded.clone(), self.config.clone())),
+            None => Cow::Borrowed(self),
+        }
+    }
+
+    /// The tokens of `on_any_tape`, which running the passes again turns back into both
+    /// versions.
+    pub(crate) fn any_tape_tokens(&self) -> &[Token] {
+        self.seeded.as_deref().unwrap_or(&self.inst)
+    }
+
+    /// `on_any_tape`, taking the program.
+    pub(crate) fn into_any_tape(self) -> Self {
+        match self.seeded {
+            Some(seeded) => Self::with_tokens(seeded, self.config),
+            None => self,
+        }
+    }
+
+    /// `on_any_tape` when `config` brings its own tape, and the program itself otherwise.
+    pub(crate) fn for_tape(&self, config: &ExecConfig) -> Cow<'_, Self> {
+        match config.tape {
+            Some(_) => self.on_any_tape(),
+            None => Cow::Borrowed(self),
+        }
+    }
+
+    /// Parses one token per command and skips every optimization, so the backends can be
+    /// checked against the optimized program on the same source.
+    pub fn unoptimized<I: IntoIterator<Item=char>>(stream: I) -> Result<Self, &'static str> {
+        let mut inst: Vec<Token> = stream.into_iter().filter_map(|c| match c {
+            '+' => Some(Token::Add(1, 0)),
+            '-' => Some(Token::Add(-1, 0)),
+            '>' => Some(Token::Shift(1)),
+            '<' => Some(Token::Shift(-1)),
+            ',' => Some(Token::Input(0)),
+            '.' => Some(Token::Output(0)),
+            '[' => Some(Token::LoopBegin(0)),
+            ']' => Some(Token::LoopEnd(0)),
+            _ => None,
+        }).collect();
+        inst.push(Token::End);
+        Self::with_tokens(inst, ExecConfig::default()).build_jump_addr()
+    }
+
+    /// Parses like `unoptimized` and sets `CellArith::Saturating`, which the folding
+    /// done by `new` would break: `+++--` is not `+` when the cell clamps at 255.
+    pub fn saturating<I: IntoIterator<Item=char>>(stream: I) -> Result<Self, &'static str> {
+        let mut interpreter = Self::unoptimized(stream)?;
+        interpreter.config.arith = CellArith::Saturating;
+        Ok(interpreter)
+    }
+
+    pub fn tokens(&self) -> &[Token] {
+        &self.inst
+    }
+
+    /// The token indices of each `LoopBegin` and its matching `LoopEnd`, in order of
+    /// `LoopBegin`, decoded from the loop operands.
+    pub fn loop_pairs(&self) -> Vec<(usize, usize)> {
+        self.inst.iter().enumerate().filter_map(|(i, token)| match *token {
+            Token::LoopBegin(label) => Some((i, (i as i32 + label - 1) as usize)),
+            _ => None,
+        }).collect()
+    }
+
+    /// The number of optimized tokens, `End` included.
+    pub fn token_count(&self) -> usize {
+        self.inst.len()
+    }
+
+    /// Roughly how many bytes of code `compile` emits, from typical sizes per token
+    /// measured on unchecked, wrapping code. Bounds checks and saturating arithmetic
+    /// add to it.
+    pub fn estimated_code_size(&self) -> usize {
+        self.inst.iter().map(|token| match token {
+            Token::AddTo(..) => 6,
+            Token::Add(..) | Token::Clear(_) | Token::Set(..) | Token::Shift(_) => 7,
+            Token::LoopBegin(_) | Token::LoopEnd(_) => 9,
+            Token::Mul(..) => 12,
+            Token::Output(_) => 22,
+            Token::Scan(_) => 25,
+            Token::Input(_) => 26,
+            Token::OutputBytes(literal) => 38 + literal.len(),
+            Token::Nop => 0,
+            // With the prologue.
+            Token::End => 57,
+        }).sum()
+    }
+
+    /// A program running `self` and then `other`, starting wherever `self` left the
+    /// pointer. The token streams are spliced as they are, so nothing is optimized across
+    /// the join; pass the result's tokens through `from_tokens` to do that. Settings are
+    /// taken from `self`. `other` starts on whatever `self` left on the tape, so its
+    /// tokens are the ones that assume nothing about it.
+    pub fn concat(&self, other: &Self) -> Self {
+        let other = other.on_any_tape();
+        let splice = |first: &[Token]| {
+            let mut inst = first[..first.len() - 1].to_vec();
+            inst.extend_from_slice(&other.inst);
+            inst
+        };
+        Self {
+            inst: splice(&self.inst),
+            seeded: self.seeded.as_deref().map(splice),
+            config: self.config.clone(),
+        }
+    }
+
+    /// Whether the program has any `,`; output of a program that doesn't depends only
+    /// on its source and can be memoized.
+    pub fn reads_input(&self) -> bool {
+        self.inst.iter().any(|token| matches!(token, Token::Input(_)))
+    }
+
+    pub fn writes_output(&self) -> bool {
+        self.inst.iter().any(|token| matches!(token, Token::Output(_) | Token::OutputBytes(_)))
+    }
+
+    /// The options `run`, `compile` and the other entry points without an explicit
+    /// config use.
+    pub fn config(&self) -> &ExecConfig {
+        &self.config
+    }
+
+    pub fn config_mut(&mut self) -> &mut ExecConfig {
+        &mut self.config
+    }
+
+    pub fn flush_policy(&self) -> FlushPolicy {
+        self.config.flush
+    }
+
+    /// Sets when both `run` and `compile` flush the writer; defaults to every byte.
+    pub fn set_flush_policy(&mut self, flush: FlushPolicy) {
+        self.config.flush = flush;
+    }
+
+    pub fn eof_policy(&self) -> EofPolicy {
+        self.config.eof
+    }
+
+    /// Sets what both `run` and `compile` store on a `,` past the end of the input;
+    /// defaults to zero.
+    pub fn set_eof_policy(&mut self, eof: EofPolicy) {
+        self.config.eof = eof;
+    }
+
+    /// Makes `run` print each token it executes to the config's `debug` writer, stderr
+    /// by default, with its index, the pointer and the current cell. The JIT is
+    /// unaffected.
+    pub fn set_trace(&mut self, trace: bool) {
+        self.config.trace = trace;
+    }
+
+    /// Sends the trace to `writer` instead of stderr, leaving the program's output
+    /// writer to the program.
+    pub fn set_debug_writer(&mut self, writer: impl Write + Send + 'static) {
+        self.config.debug = DebugWriter::new(writer);
+    }
+
+    /// Moves every `Shift` forward to the next loop boundary or `End`, adding it to the
+    /// offsets of the tokens it passes, so a straight-line block moves the pointer at
+    /// most once. Shifts that net to zero are dropped.
+    fn defer_shifts(inst: Vec<Token>) -> Vec<Token> {
+        let mut deferred = Vec::with_capacity(inst.len());
+        let mut pending = 0;
+        for token in inst {
+            let token = match token {
+                Token::Shift(n) => {
+                    pending += n;
+                    continue;
+                }
+                Token::LoopBegin(_) | Token::LoopEnd(_) | Token::Scan(_) | Token::End => {
+                    if pending != 0 {
+                        deferred.push(Token::Shift(pending));
+                        pending = 0;
+                    }
+                    token
+                }
+                Token::Add(n, shift) => Token::Add(n, shift + pending),
+                Token::Mul(n, shift, base) => Token::Mul(n, shift + pending, base + pending),
+                Token::AddTo(to, from) => Token::AddTo(to + pending, from + pending),
+                Token::Clear(shift) => Token::Clear(shift + pending),
+                Token::Set(n, shift) => Token::Set(n, shift + pending),
+                Token::Input(shift) => Token::Input(shift + pending),
+                Token::Output(shift) => Token::Output(shift + pending),
+                Token::OutputBytes(_) | Token::Nop => token,
+            };
+            deferred.push(token);
+        }
+        deferred
+    }
+
+    /// Runs the token-level passes. With `fresh`, cells nothing has written yet are
+    /// taken to be zero until the first loop, which only holds on a blank tape.
+    pub(crate) fn optimize(inst: Vec<Token>, fresh: bool) -> Vec<Token> {
+        let inst = Self::drop_redundant_clears(Self::fuse_sets(Self::defer_shifts(inst)), fresh);
+        Self::collapse_scans(Self::compact(Self::fold_outputs(inst, fresh)))
+    }
+
+    /// `optimize` for the tokens before a `LoopBegin` whose loop is still to come. Every
+    /// pass forgets what it knows at a `LoopBegin`, so these passes and those over the
+    /// rest from the `LoopBegin` on give the passes over the whole. The `LoopBegin` put
+    /// in for the rest flushes what the passes hold back, such as a deferred shift.
+    pub(crate) fn optimize_head(inst: &[Token], fresh: bool) -> Vec<Token> {
+        let mut inst = inst.to_vec();
+        inst.push(Token::LoopBegin(0));
+        let mut optimized = Self::optimize(inst, fresh);
+        optimized.pop();
+        optimized
+    }
+
+    /// Turns each loop whose body is a single `Shift` into a `Scan`.
+    fn collapse_scans(inst: Vec<Token>) -> Vec<Token> {
+        let mut collapsed = Vec::with_capacity(inst.len());
+        for token in inst {
+            if let Token::LoopEnd(_) = token {
+                if let [.., Token::LoopBegin(_), Token::Shift(n)] = collapsed[..] {
+                    collapsed.truncate(collapsed.len() - 2);
+                    collapsed.push(Token::Scan(n));
+                    continue;
+                }
+            }
+            collapsed.push(token);
+        }
+        collapsed
+    }
+
+    /// Drops every `Nop`. Loop operands go stale, so this runs before `build_jump_addr`.
+    fn compact(mut inst: Vec<Token>) -> Vec<Token> {
+        inst.retain(|token| !matches!(token, Token::Nop));
+        inst
+    }
+
+    /// Drops `Clear` tokens whose cell is already known to be zero: cells untouched since
+    /// the start when `fresh`, cells cleared earlier with no write since, and the cell a
+    /// loop just exited on. What is known is discarded at every loop boundary.
+    fn drop_redundant_clears(inst: Vec<Token>, mut fresh: bool) -> Vec<Token> {
+        let mut kept = Vec::with_capacity(inst.len());
+        let mut zero = HashMap::new();
+        let mut pos = 0;
+        for token in inst {
+            match token {
+                Token::Clear(shift) => {
+                    if *zero.get(&(pos + shift)).unwrap_or(&fresh) {
+                        continue;
+                    }
+                    zero.insert(pos + shift, true);
+                }
+                Token::Set(n, shift) => { zero.insert(pos + shift, n == 0); }
+                Token::Add(_, shift) | Token::Mul(_, shift, _) | Token::AddTo(shift, _) | Token::Input(shift) => {
+                    zero.insert(pos + shift, false);
+                }
+                Token::Shift(shift) => pos += shift,
+                Token::LoopBegin(_) => {
+                    zero.clear();
+                    fresh = false;
+                }
+                Token::LoopEnd(_) | Token::Scan(_) => {
+                    zero.clear();
+                    fresh = false;
+                    zero.insert(pos, true);
+                }
+                Token::Output(_) | Token::OutputBytes(_) | Token::Nop | Token::End => {}
+            }
+            kept.push(token);
+        }
+        kept
+    }
+
+    /// Replaces each run of two or more `Output`s whose values are known from the
+    /// straight-line code before them with one `OutputBytes` at the first of them. Only
+    /// arithmetic sits between the outputs of a run, so no other I/O is reordered.
+    fn fold_outputs(inst: Vec<Token>, mut fresh: bool) -> Vec<Token> {
+        fn known(cells: &HashMap<i32, Option<u8>>, fresh: bool, cell: i32) -> Option<u8> {
+            match cells.get(&cell) {
+                Some(value) => *value,
+                None if fresh => Some(0),
+                None => None,
+            }
+        }
+        fn close(folded: &mut [Token], group: Option<(usize, i32)>) {
+            if let Some((at, shift)) = group {
+                if matches!(&folded[at], Token::OutputBytes(literal) if literal.len() == 1) {
+                    folded[at] = Token::Output(shift);
+                }
+            }
+        }
+        // With `fresh`, cells nothing has written yet are known to be zero until the first
+        // loop. Only written cells are folded, so an output past the end of the tape still
+        // fails.
+        let mut cells = HashMap::new();
+        let mut folded = Vec::with_capacity(inst.len());
+        let mut group = None;
+        let mut pos = 0;
+        for token in inst {
+            let written = match token {
+                Token::Add(n, shift) => Some((pos + shift, known(&cells, fresh, pos + shift).map(|v| v.wrapping_add(n as u8)))),
+                Token::Mul(n, shift, base) => {
+                    let value = known(&cells, fresh, pos + shift).zip(known(&cells, fresh, pos + base));
+                    Some((pos + shift, value.map(|(v, b)| v.wrapping_add(b.wrapping_mul(n as u8)))))
+                }
+                Token::AddTo(to, from) => {
+                    let value = known(&cells, fresh, pos + to).zip(known(&cells, fresh, pos + from));
+                    Some((pos + to, value.map(|(v, f)| v.wrapping_add(f))))
+                }
+                Token::Clear(shift) => Some((pos + shift, Some(0))),
+                Token::Set(n, shift) => Some((pos + shift, Some(n))),
+                Token::Input(shift) => Some((pos + shift, None)),
+                _ => None,
+            };
+            if let Some((cell, value)) = written {
+                cells.insert(cell, value);
+            }
+            match token {
+                Token::Shift(shift) => pos += shift,
+                Token::Output(shift) => if let Some(&Some(value)) = cells.get(&(pos + shift)) {
+                    match group {
+                        Some((at, _)) => if let Token::OutputBytes(literal) = &mut folded[at] {
+                            literal.push(value);
+                        }
+                        None => {
+                            group = Some((folded.len(), shift));
+                            folded.push(Token::OutputBytes(vec![value]));
+                        }
+                    }
+                    continue;
+                } else {
+                    close(&mut folded, group.take());
+                }
+                Token::Input(_) | Token::OutputBytes(_) | Token::End => close(&mut folded, group.take()),
+                Token::LoopBegin(_) | Token::LoopEnd(_) | Token::Scan(_) => {
+                    close(&mut folded, group.take());
+                    cells.clear();
+                    fresh = false;
+                    if let Token::LoopEnd(_) | Token::Scan(_) = token {
+                        cells.insert(pos, Some(0));
+                    }
+                }
+                _ => {}
+            }
+            folded.push(token);
+        }
+        close(&mut folded, group);
+        folded
+    }
+
+    /// Turns `Clear(s)` followed by `Add(n, s)` into a single `Set`. Adds, clears and sets
+    /// of other cells commute with both, so they may sit in between, as may the `Nop`
+    /// each absorbed `Add` is left as.
+    fn fuse_sets(mut inst: Vec<Token>) -> Vec<Token> {
+        for i in 0..inst.len() {
+            let shift = match inst[i] {
+                Token::Clear(shift) => shift,
+                _ => continue,
+            };
+            for j in i + 1..inst.len() {
+                match inst[j] {
+                    Token::Add(n, s) if s == shift => {
+                        inst[i] = Token::Set(n as u8, shift);
+                        inst[j] = Token::Nop;
+                        break;
+                    }
+                    Token::Add(_, s) | Token::Clear(s) | Token::Set(_, s) if s != shift => {}
+                    Token::Nop => {}
+                    _ => break,
+                }
+            }
+        }
+        inst
+    }
+
+    pub(crate) fn build_jump_addr(self) -> Result<Self, &'static str> {
+        Ok(Self {
+            inst: Self::link(self.inst)?,
+            seeded: self.seeded.map(Self::link).transpose()?,
+            ..self
+        })
+    }
+
+    fn link(inst: Vec<Token>) -> Result<Vec<Token>, &'static str> {
+        let mut opt = Vec::with_capacity(inst.len());
+        let mut stack = Vec::new();
+        for (i, token) in inst.into_iter().enumerate() {
+            match token {
+                Token::LoopBegin(_) => {
+                    stack.push(i);
+                    opt.push(Token::LoopBegin(0));
+                }
+                Token::LoopEnd(_) => {
+                    let pos = stack.pop().ok_or("[ missing.")?;
+                    let shift = i as i32 - pos as i32;
+                    opt[pos] = Token::LoopBegin(shift + 1);
+                    opt.push(Token::LoopEnd(1 - shift));
+                }
+                tk => opt.push(tk),
+            }
+        }
+        if !stack.is_empty() {
+            return Err("] missing.");
+        }
+        Ok(opt)
+    }
+
+    pub fn run(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<(), ExecError> {
+        self.run_inspect(reader, writer).map(|_| ())
+    }
+
+    /// Runs the program pulling each input byte from `input`; for the JIT, wrap the
+    /// closure with `io::from_fn` instead.
+    pub fn run_fn<F: FnMut() -> Option<u8>>(&self, input: F, writer: &mut dyn Write) -> Result<(), ExecError> {
+        self.run(&mut crate::io::from_fn(input), writer)
+    }
+
+    /// Runs the program on an in-memory input and collects everything it prints.
+    pub fn run_bytes(&self, input: &[u8]) -> Result<Vec<u8>, ExecError> {
+        let mut output = VecOutput::new();
+        self.run(&mut SliceInput::new(input), &mut output)?;
+        Ok(output.into_inner())
+    }
+
+    /// Like `run`, returning how many bytes were written.
+    pub fn run_counted(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<u64, ExecError> {
+        let mut counter = CountingWriter::new(writer);
+        self.run(reader, &mut counter)?;
+        Ok(counter.written())
+    }
+
+    /// Like `run_bytes`, with the output decoded as UTF-8, replacing invalid sequences.
+    pub fn run_str(&self, input: &str) -> Result<String, ExecError> {
+        let output = self.run_bytes(input.as_bytes())?;
+        Ok(String::from_utf8_lossy(&output).into_owned())
+    }
+
+    /// Like `run`, but hands back the final tape so results left in memory can be read.
+    pub fn run_inspect(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<Tape, ExecError> {
+        self.run_with(&self.config, reader, writer)
+    }
+
+    /// Like `run`, returning the `Tape::histogram` of the final tape.
+    pub fn run_histogram(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<[u64; 256], ExecError> {
+        Ok(self.run_inspect(reader, writer)?.histogram())
+    }
+
+    /// Like `run_inspect`, but starts from `tape`'s cells and pointer instead of a blank
+    /// tape, e.g. one seeded with `Tape::from_cells` or left by an earlier run.
+    pub fn run_on(&self, tape: Tape, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<Tape, ExecError> {
+        let config = ExecConfig {
+            tape: Some(tape),
+            ..self.config.clone()
+        };
+        self.run_with(&config, reader, writer)
+    }
+
+    /// Like `run_inspect`, but stops with `ExecError::Cancelled` soon after `cancel` is
+    /// triggered. The flag is polled every `CANCEL_CHECK_INTERVAL` loop back-edges, which
+    /// any non-terminating program keeps taking.
+    pub fn run_cancellable(&self, reader: &mut dyn Read, writer: &mut dyn Write, cancel: &CancelHandle) -> Result<Tape, ExecError> {
+        let config = ExecConfig {
+            cancel: Some(cancel.clone()),
+            ..self.config.clone()
+        };
+        self.run_with(&config, reader, writer)
+    }
+
+    /// Interprets the program under `config` instead of the interpreter's own settings
+    /// and hands back the final tape.
+    pub fn run_with(&self, config: &ExecConfig, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<Tape, ExecError> {
+        let mut tape = config.tape.clone().unwrap_or_default();
+        self.for_tape(config).interpret(config, &mut tape, reader, writer, &mut Meter::default())?;
+        Ok(tape)
+    }
+
+    /// A rough count of tokens executed by one run, assuming every loop iterates
+    /// `ASSUMED_LOOP_ITERATIONS` times.
+    pub fn estimated_steps(&self) -> u64 {
+        let mut steps = 0u64;
+        let mut weight = 1u64;
+        let mut weights = Vec::new();
+        for token in &self.inst {
+            steps = steps.saturating_add(weight);
+            match token {
+                Token::LoopBegin(_) => {
+                    weights.push(weight);
+                    weight = weight.saturating_mul(ASSUMED_LOOP_ITERATIONS);
+                }
+                Token::LoopEnd(_) => weight = weights.pop().unwrap_or(1),
+                _ => {}
+            }
+        }
+        steps
+    }
+
+    /// Token indices (into `tokens`) of loops that can never exit once entered: loops with
+    /// no nested loop, no input and no net shift whose body leaves the controlling cell
+    /// unchanged. Conservative, so a loop that isn't listed may still spin forever.
+    pub fn detect_infinite_loops(&self) -> Vec<usize> {
+        let mut suspects = Vec::new();
+        for (i, token) in self.inst.iter().enumerate() {
+            let end = match token {
+                Token::LoopBegin(label) => i + *label as usize - 1,
+                _ => continue,
+            };
+            let mut offset = 0;
+            let mut delta = 0i32;
+            let mut bounded = true;
+            for token in &self.inst[i + 1..end] {
+                match *token {
+                    Token::Add(n, shift) => if offset + shift == 0 {
+                        delta += n as i32;
+                    }
+                    Token::Mul(_, shift, _) | Token::AddTo(shift, _) | Token::Clear(shift) | Token::Set(_, shift) => if offset + shift == 0 {
+                        bounded = false;
+                    }
+                    Token::Shift(shift) => offset += shift,
+                    Token::Output(_) | Token::OutputBytes(_) => {}
+                    _ => bounded = false,
+                }
+            }
+            if bounded && offset == 0 && delta.rem_euclid(256) == 0 {
+                suspects.push(i);
+            }
+        }
+        suspects
+    }
+
+    /// Every byte the program can print, when each `Output` reads a cell whose value is
+    /// known statically; `None` as soon as one output depends on input or loop state.
+    /// Knowledge about cells is discarded whenever a loop may run.
+    pub fn static_output_bytes(&self) -> Option<HashSet<u8>> {
+        fn known(cells: &HashMap<i32, Option<u8>>, fresh: bool, cell: i32) -> Option<u8> {
+            match cells.get(&cell) {
+                Some(value) => *value,
+                None if fresh => Some(0),
+                None => None,
+            }
+        }
+        let mut bytes = HashSet::new();
+        let mut cells = HashMap::new();
+        let mut fresh = true;
+        let mut pos = 0;
+        let mut i = 0;
+        while i < self.inst.len() {
+            match self.inst[i] {
+                Token::Add(n, shift) => {
+                    let value = known(&cells, fresh, pos + shift).map(|v| v.wrapping_add(n as u8));
+                    cells.insert(pos + shift, value);
+                }
+                Token::Mul(n, shift, base) => {
+                    let value = known(&cells, fresh, pos + shift)
+                        .zip(known(&cells, fresh, pos + base))
+                        .map(|(v, b)| v.wrapping_add(b.wrapping_mul(n as u8)));
+                    cells.insert(pos + shift, value);
+                }
+                Token::AddTo(to, from) => {
+                    let value = known(&cells, fresh, pos + to)
+                        .zip(known(&cells, fresh, pos + from))
+                        .map(|(v, f)| v.wrapping_add(f));
+                    cells.insert(pos + to, value);
+                }
+                Token::Clear(shift) => { cells.insert(pos + shift, Some(0)); }
+                Token::Set(n, shift) => { cells.insert(pos + shift, Some(n)); }
+                Token::Shift(shift) => pos += shift,
+                Token::LoopBegin(label) => {
+                    if known(&cells, fresh, pos) == Some(0) {
+                        i += label as usize;
+                        continue;
+                    }
+                    cells.clear();
+                    fresh = false;
+                }
+                Token::LoopEnd(_) => {
+                    cells.clear();
+                    fresh = false;
+                    cells.insert(pos, Some(0));
+                }
+                Token::Scan(_) => if known(&cells, fresh, pos) != Some(0) {
+                    cells.clear();
+                    fresh = false;
+                    cells.insert(pos, Some(0));
+                }
+                Token::Input(shift) => { cells.insert(pos + shift, None); }
+                Token::Output(shift) => { bytes.insert(known(&cells, fresh, pos + shift)?); }
+                Token::OutputBytes(ref literal) => bytes.extend(literal),
+                Token::Nop => {}
+                Token::End => break,
+            }
+            i += 1;
+        }
+        Some(bytes)
+    }
+
+    /// Runs the program with whichever backend is expected to finish sooner.
+    pub fn execute(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<Timings, ExecError> {
+        self.execute_with_threshold(reader, writer, DEFAULT_JIT_THRESHOLD)
+    }
+
+    /// Like `execute`, but JIT-compiles only when `estimated_steps` reaches `threshold`
+    /// times the token count, and interprets otherwise. A threshold of 0 compiles every
+    /// program within `MAX_JIT_TOKENS`. Programs whose config sets an option the JIT
+    /// ignores, see `ExecConfig::needs_interpreter`, are always interpreted.
+    pub fn execute_with_threshold(&self, reader: &mut dyn Read, writer: &mut dyn Write, threshold: u64) -> Result<Timings, ExecError> {
+        let start = Instant::now();
+        let jit = !self.config.needs_interpreter() && self.inst.len() <= MAX_JIT_TOKENS;
+        if jit && self.estimated_steps() >= threshold.saturating_mul(self.inst.len() as u64) {
+            let compiled = self.compile();
+            let compile = start.elapsed();
+            compiled(reader, writer);
+            Ok(Timings {
+                compile: Some(compile),
+                run: start.elapsed() - compile,
+            })
+        } else {
+            self.run(reader, writer)?;
+            Ok(Timings {
+                compile: None,
+                run: start.elapsed(),
+            })
+        }
+    }
+
+    /// Pre-compiles the tokens into closures for repeated runs with `Threaded::run`,
+    /// which follow this program's EOF and flush policies. Fails if loops nest deeper
+    /// than `threaded::MAX_DEPTH`.
+    pub fn threaded(&self) -> Result<Threaded, &'static str> {
+        Threaded::new(&self.inst, &self.config)
+    }
+
+    /// Runs the program on `tape`, leaving the pointer where it stopped even on error.
+    pub(crate) fn interpret(&self, config: &ExecConfig, tape: &mut Tape, reader: &mut dyn Read, writer: &mut dyn Write, meter: &mut Meter) -> Result<(), ExecError> {
+        // Inspection (tracing or watching), step counting and saturation are separate
+        // instantiations so the plain loop carries no checks for them.
+        let inspect = config.trace || meter.watch.is_some() || meter.pointers.is_some() || meter.pause_output;
+        let metered = meter.count_steps || config.max_steps.is_some() || meter.budget.is_some();
+        let result = config.eof.with_reader(reader, |reader| match (config.arith, inspect, metered) {
+            (CellArith::Wrapping, false, false) => self.dispatch::<false, false, false>(config, tape, reader, writer, meter),
+            (CellArith::Wrapping, false, true) => self.dispatch::<false, true, false>(config, tape, reader, writer, meter),
+            (CellArith::Wrapping, true, false) => self.dispatch::<true, false, false>(config, tape, reader, writer, meter),
+            (CellArith::Wrapping, true, true) => self.dispatch::<true, true, false>(config, tape, reader, writer, meter),
+            (CellArith::Saturating, false, false) => self.dispatch::<false, false, true>(config, tape, reader, writer, meter),
+            (CellArith::Saturating, false, true) => self.dispatch::<false, true, true>(config, tape, reader, writer, meter),
+            (CellArith::Saturating, true, false) => self.dispatch::<true, false, true>(config, tape, reader, writer, meter),
+            (CellArith::Saturating, true, true) => self.dispatch::<true, true, true>(config, tape, reader, writer, meter),
+        });
+        if config.reset_pointer && result.is_ok() && meter.yielded.is_none() {
+            tape.set_pointer(0);
+        }
+        result
+    }
+
+    #[inline(always)]
+    pub(crate) fn add_cell<const SATURATING: bool>(cell: u8, n: i32) -> u8 {
+        if SATURATING {
+            (cell as i32 + n).clamp(0, 0xff) as u8
+        } else {
+            cell.wrapping_add(n as u8)
+        }
+    }
+
+    fn dispatch<const INSPECT: bool, const METERED: bool, const SATURATING: bool>(&self, config: &ExecConfig, tape: &mut Tape, reader: &mut dyn Read, writer: &mut dyn Write, meter: &mut Meter) -> Result<(), ExecError> {
+        let mut pos = tape.pointer() as i32;
+        let mut steps = 0;
+        let max_steps = config.max_steps.unwrap_or(u64::MAX);
+        let cancel = config.cancel.as_ref();
+        let deadline = config.timeout.map(|timeout| Instant::now() + timeout);
+        let flush = config.flush;
+        let eof = config.eof;
+        let polling = cancel.is_some() || deadline.is_some();
+        let mut debug = (INSPECT && config.trace).then(|| config.debug.lock());
+        let mut buffer = tape.cells_mut();
+        // The cell found outside the tape by `e` once a growable tape has grown to take
+        // it, or out of the loop with `e` or `TapeLimitExceeded`.
+        macro_rules! grow {
+            ($e:expr) => {{
+                let grown = tape.grow_past($e);
+                buffer = tape.cells_mut();
+                match grown {
+                    Ok(index) => index,
+                    Err(e) => break Err(e),
+                }
+            }};
+        }
+        // The index of the cell at `shift`, or out of the loop with `OutOfBounds`.
+        macro_rules! cell {
+            ($shift:expr) => {
+                match Self::index(pos, $shift, buffer.len()) {
+                    Ok(index) => index,
+                    Err(e) => grow!(e),
+                }
+            };
+        }
+        let mut i = meter.start as i32;
+        let mut countdown = CANCEL_CHECK_INTERVAL;
+        let result = loop {
+            let token = match self.inst.get(i as usize) {
+                Some(token) => token,
+                None => break Err(ExecError::BadJump { index: i }),
+            };
+            if METERED {
+                if steps == max_steps {
+                    break Err(ExecError::StepLimit { steps });
+                }
+                if meter.budget == Some(steps) {
+                    meter.yielded = Some(i as usize);
+                    break Ok(());
+                }
+                steps += 1;
+            }
+            if INSPECT {
+                if meter.pause_output && matches!(token, Token::Output(_) | Token::OutputBytes(_)) && !mem::take(&mut meter.resume_output) {
+                    break Err(ExecError::Paused { index: i as usize });
+                }
+                if let Some(debug) = debug.as_mut() {
+                    let cell = buffer.get(pos as usize).map_or(String::from("-"), u8::to_string);
+                    writeln!(debug, "{:>6} {:?} pointer={} cell={}", i, token, pos, cell).unwrap();
+                }
+                if let Some(watch) = meter.watch.as_mut() {
+                    watch.check(i, token, pos, buffer, Access::Read);
+                }
+                if let Some(pointers) = meter.pointers.as_mut() {
+                    pointers.record(token, pos, buffer.len());
+                }
+            }
+            match *token {
+                Token::Add(n, shift) => {
+                    let cell = &mut buffer[cell!(shift)];
+                    *cell = Self::add_cell::<SATURATING>(*cell, n as i32);
+                }
+                Token::Mul(n, shift, base) => {
+                    let base_n = buffer[cell!(base)];
+                    if base_n != 0 {
+                        let cell = &mut buffer[cell!(shift)];
+                        *cell = Self::add_cell::<SATURATING>(*cell, base_n as i32 * n as i32);
+                    }
+                }
+                Token::AddTo(to, from) => {
+                    let from_n = buffer[cell!(from)];
+                    if from_n != 0 {
+                        let cell = &mut buffer[cell!(to)];
+                        *cell = Self::add_cell::<SATURATING>(*cell, from_n as i32);
+                    }
+                }
+                Token::Clear(shift) => buffer[cell!(shift)] = 0,
+                Token::Set(n, shift) => buffer[cell!(shift)] = n,
+                Token::Shift(shift) => pos += shift,
+                Token::LoopBegin(label) => if buffer[cell!(0)] == 0 {
+                    i += label;
+                    continue;
+                }
+                Token::LoopEnd(label) => if buffer[cell!(0)] != 0 {
+                    if polling {
+                        countdown -= 1;
+                        if countdown == 0 {
+                            if cancel.is_some_and(CancelHandle::is_cancelled) {
+                                break Err(ExecError::Cancelled);
+                            }
+                            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                                break Err(ExecError::TimedOut);
+                            }
+                            countdown = CANCEL_CHECK_INTERVAL;
+                        }
+                    }
+                    i += label;
+                    continue;
+                }
+                // Cells a tape grows by are zero, so a scan that ran off the end stops
+                // on the first of them it lands on.
+                Token::Scan(n) => match Self::scan(buffer, pos, n) {
+                    Ok(found) => pos = found,
+                    Err(e) => pos = grow!(e) as i32,
+                }
+                Token::Input(shift) => {
+                    let index = cell!(shift);
+                    buffer[index] = match eof.read(reader) {
+                        Some(byte) => byte,
+                        None => break Err(ExecError::NeedsInput { index: i as usize }),
+                    };
+                }
+                Token::Output(shift) => {
+                    let byte = buffer[cell!(shift)];
+                    write_out(writer, &[byte]).unwrap();
+                    if flush.should_flush(byte) {
+                        flush_out(writer).unwrap();
+                    }
+                }
+                Token::OutputBytes(ref literal) => {
+                    write_out(writer, literal).unwrap();
+                    if literal.iter().any(|&byte| flush.should_flush(byte)) {
+                        flush_out(writer).unwrap();
+                    }
+                }
+                Token::Nop => {}
+                Token::End => break Ok(()),
+            }
+            if INSPECT {
+                if let Some(watch) = meter.watch.as_mut() {
+                    watch.check(i, token, pos, buffer, Access::Write);
+                }
+            }
+            i += 1;
+        };
+        meter.steps = steps;
+        meter.pointer = pos;
+        if pos >= 0 {
+            tape.set_pointer(pos as usize);
+        }
+        flush_out(writer).unwrap();
+        if let Some(debug) = debug.as_mut() {
+            debug.flush().unwrap();
+        }
+        result
+    }
+
+    /// Where `Scan(n)` from `pos` stops. Steps of one search with `memchr`; a scan that
+    /// leaves the tape fails at the first cell outside it.
+    pub(crate) fn scan(buffer: &[u8], pos: i32, n: i32) -> Result<i32, ExecError> {
+        let start = Self::index(pos, 0, buffer.len())?;
+        let found = match n {
+            1 => memchr::memchr(0, &buffer[start..]).map(|k| (start + k) as i32),
+            -1 => memchr::memrchr(0, &buffer[..=start]).map(|k| k as i32),
+            _ => {
+                let mut cell = pos;
+                while buffer[cell as usize] != 0 {
+                    cell += n;
+                    Self::index(cell, 0, buffer.len())?;
+                }
+                Some(cell)
+            }
+        };
+        found.ok_or(ExecError::OutOfBounds { pos: if n > 0 { buffer.len() as i32 } else { -1 } })
+    }
+
+    pub(crate) fn index(pos: i32, shift: i32, len: usize) -> Result<usize, ExecError> {
+        let cell = pos + shift;
+        if cell < 0 || cell as usize >= len {
+            return Err(ExecError::OutOfBounds { pos: cell });
+        }
+        Ok(cell as usize)
+    }
+
+    /// JIT-compiles the program. Assembly is linear in the token count, with the code
+    /// assembled in one piece; see `MAX_JIT_TOKENS` for what long programs cost.
+    pub fn compile(&self) -> CompiledProgram {
+        self.compile_with(&self.config)
+    }
+
+    /// Like `compile`, but every call of the closure starts from a copy of `tape`.
+    pub fn compile_on(&self, tape: Tape) -> CompiledProgram {
+        let config = ExecConfig {
+            tape: Some(tape),
+            ..self.config.clone()
+        };
+        self.compile_with(&config)
+    }
+
+    /// Compiles the program under `config` instead of the interpreter's own settings.
+    /// The closure panics if the reader or writer fails, and with `bounds_check` set, on
+    /// an out-of-bounds access.
+    pub fn compile_with(&self, config: &ExecConfig) -> CompiledProgram {
+        let tape = config.tape.clone().unwrap_or_default();
+        let start = Instant::now();
+        let assembled = self.for_tape(config).assemble(config);
+        let assembly = start.elapsed();
+        let code_size = assembled.buf.len();
+        CompiledProgram {
+            run: Box::new(move |reader: &mut dyn Read, writer: &mut dyn Write| {
+                if let Err(e) = assembled.run(&tape, reader, writer) {
+                    panic!("{}", e);
+                }
+            }),
+            code_size,
+            loops: self.inst.iter().filter(|token| matches!(token, Token::LoopBegin(_))).count(),
+            assembly,
+        }
+    }
+
+    /// Like `compile`, but the code checks every cell access against the tape and the
+    /// closure fails with `ExecError::OutOfBounds` instead of touching memory outside it.
+    pub fn compile_checked(&self) -> CheckedCompiled {
+        let config = ExecConfig {
+            bounds_check: true,
+            ..self.config.clone()
+        };
+        let tape = config.tape.clone().unwrap_or_default();
+        let assembled = self.for_tape(&config).assemble(&config);
+        Box::new(move |reader: &mut dyn Read, writer: &mut dyn Write| {
+            assembled.run(&tape, reader, writer)
+        })
+    }
+
+    /// Like `compile`, but the code also counts what it does and each call returns the
+    /// counts. Loop-heavy programs run around three times slower.
+    pub fn compile_instrumented(&self) -> InstrumentedCompiled {
+        let counters = Box::new(UnsafeCell::new(JitStats::default()));
+        let tape = self.config.tape.clone().unwrap_or_default();
+        let assembled = self.for_tape(&self.config).assemble_counted(&self.config, Some(counters.get()));
+        Box::new(move |reader: &mut dyn Read, writer: &mut dyn Write| {
+            // SAFETY: the counters are only accessed through raw pointers, by the code and
+            // here, and the closure is not `Sync`, so no two threads touch them at once.
+            unsafe {
+                *counters.get() = JitStats::default();
+            }
+            if let Err(e) = assembled.run(&tape, reader, writer) {
+                panic!("{}", e);
+            }
+            unsafe { *counters.get() }
+        })
+    }
+
+    /// Like `compile`, but hands over the code itself instead of a closure owning it.
+    /// The configured tape is not captured: every run gets the caller's, so the code makes
+    /// no assumption about what is on it.
+    pub fn compile_buffer(&self) -> ProgramBuffer {
+        let Assembled { buf, entry, eof, .. } = self.on_any_tape().assemble(&self.config);
+        let len = self.config.tape.as_ref().map_or(TAPE_SIZE, |tape| tape.cells().len());
+        ProgramBuffer {
+            buf,
+            entry,
+            checked_len: if self.config.bounds_check { Some(len) } else { None },
+            eof,
+        }
+    }
+
+    /// Assembles the program. The cell pointer (tape base plus offset) stays live in
+    /// `rbx`, so every access is a single `[rbx + shift]`; reader and writer live in the
+    /// callee-saved `r12`/`r13`. Under `bounds_check` the tape's first and one-past-last
+    /// cells are kept in `r14`/`r15`, and an access outside them returns its cell index
+    /// with bit 32 set. Otherwise the code returns the cell the pointer ended on.
+    pub(crate) fn assemble(&self, config: &ExecConfig) -> Assembled {
+        self.assemble_counted(config, None)
+    }
+
+    /// `assemble`, adding to the fields of `*counters`, if given, as the code runs.
+    fn assemble_counted(&self, config: &ExecConfig, counters: Option<*mut JitStats>) -> Assembled {
+        let checked = config.bounds_check;
+        let saturating = config.arith == CellArith::Saturating;
+        let len = config.tape.as_ref().map_or(TAPE_SIZE, |tape| tape.cells().len());
+        let mut ops = Assembler::new().unwrap();
+        // Outside Windows the C convention is System V, whose first three arguments are
+        // moved into the win64 registers before falling through into the prologue.
+        let c_entry = ops.offset();
+        if cfg!(not(windows)) {
+            dynasm!(ops
+                ; mov r8, rdx
+                ; mov rdx, rsi
+                ; mov rcx, rdi
+            );
+        }
+        let start = ops.offset();
+        let mut labels = Vec::new();
+        let putchar = match config.flush {
+            FlushPolicy::PerChar => Self::putchar_flush as *const (),
+            FlushPolicy::PerNewline => Self::putchar_line as *const (),
+            FlushPolicy::AtEnd => Self::putchar as *const (),
+        };
+        // Without I/O nothing is called, so the reader and writer, the shadow space and
+        // the saved tape base (kept in `r9` instead) are left out.
+        let io = self.reads_input() || self.writes_output();
+        dynasm!(ops
+            ; push rbp
+            ; mov rbp, rsp
+            ; push rbx
+        );
+        if io {
+            dynasm!(ops
+                ; push r12
+                ; push r13
+            );
+        }
+        if checked {
+            dynasm!(ops
+                ; push r14
+                ; push r15
+                ; mov r14, r9
+                ; mov r15, QWORD len as _
+                ; add r15, r9
+            );
+        }
+        dynasm!(ops
+            ; mov rbx, rcx
+        );
+        if io {
+            dynasm!(ops
+                ; sub rsp, 0x28
+                ; mov r12, rdx
+                ; mov r13, r8
+            );
+        }
+        if io && !checked {
+            // The tape base, for the final cell index `End` returns.
+            dynasm!(ops
+                ; mov [rsp + 0x20], r9
+            );
+        }
+        // Jumps to the out-of-bounds exit unless `[rbx + shift]` is on the tape.
+        let check = |ops: &mut Assembler, shift: i32| {
+            if checked {
+                dynasm!(ops
+                    ; lea rax, [rbx + shift]
+                    ; cmp rax, r14
+                    ; jb ->out_of_bounds
+                    ; cmp rax, r15
+                    ; jae ->out_of_bounds
+                );
+            }
+        };
+        // Adds `n` to the counter at byte `offset` of `JitStats`, clobbering `rax`.
+        let count = |ops: &mut Assembler, offset: i32, n: i32| {
+            if let Some(counters) = counters {
+                dynasm!(ops
+                    ; mov rax, QWORD counters as _
+                    ; add QWORD [rax + offset], n
+                );
+            }
+        };
+        // Stored after the code, each at its label.
+        let mut literals = Vec::new();
+        // A collapsed loop's counter cell is loaded into `ecx` once and shared by the run of
+        // `Mul`/`AddTo` tokens reading it, none of which store to it.
+        let mut loaded = None;
+        for i in 0..self.inst.len() {
+            count(&mut ops, 0, 1);
+            if let Token::Mul(_, _, base) | Token::AddTo(_, base) = self.inst[i] {
+                if loaded != Some(base) {
+                    check(&mut ops, base);
+                    dynasm!(ops
+                        ; movzx ecx, BYTE [rbx + base]
+                    );
+                    loaded = Some(base);
+                }
+            } else {
+                loaded = None;
+            }
+            match self.inst[i] {
+                // The target of a collapsed loop whose counter is zero is left alone, and
+                // may be off the tape.
+                Token::Mul(_, shift, _) | Token::AddTo(shift, _) => {
+                    dynasm!(ops
+                        ; test ecx, ecx
+                        ; jz >untouched
+                    );
+                    check(&mut ops, shift);
+                }
+                Token::Add(_, shift) | Token::Clear(shift) | Token::Set(_, shift) | Token::Input(shift)
+                | Token::Output(shift) => check(&mut ops, shift),
+                Token::LoopBegin(_) | Token::LoopEnd(_) | Token::Scan(_) => check(&mut ops, 0),
+                Token::Shift(_) | Token::OutputBytes(_) | Token::Nop | Token::End => {}
+            }
+            match self.inst[i] {
+                Token::Add(n, shift) if saturating => {
+                    if n.unsigned_abs() >= 0xff {
+                        dynasm!(ops
+                            ; mov BYTE [rbx + shift], if n > 0 { -1 } else { 0 }
+                        );
+                    } else if n > 0 {
+                        dynasm!(ops
+                            ; add BYTE [rbx + shift], n as _
+                            ; jnc >done
+                            ; mov BYTE [rbx + shift], -1
+                            ; done:
+                        );
+                    } else {
+                        dynasm!(ops
+                            ; sub BYTE [rbx + shift], -n as _
+                            ; jnc >done
+                            ; mov BYTE [rbx + shift], 0
+                            ; done:
+                        );
+                    }
+                }
+                Token::Add(n, shift) => {
+                    dynasm!(ops
+                        ; add BYTE [rbx + shift], n as _
+                    );
+                }
+                Token::Mul(_, shift, _) | Token::AddTo(shift, _) if saturating => {
+                    let n = match self.inst[i] {
+                        Token::Mul(n, ..) => n as i32,
+                        _ => 1,
+                    };
+                    // Sums in 32 bits, then clamps to 0..=255 before storing the low byte.
+                    dynasm!(ops
+                        ; movzx eax, BYTE [rbx + shift]
+                        ; imul edx, ecx, n
+                        ; add eax, edx
+                        ; xor edx, edx
+                        ; test eax, eax
+                        ; cmovs eax, edx
+                        ; mov edx, 0xff
+                        ; cmp eax, edx
+                        ; cmova eax, edx
+                        ; mov BYTE [rbx + shift], al
+                    );
+                }
+                Token::Mul(n, shift, _) => {
+                    dynasm!(ops
+                        ; imul eax, ecx, n as i32
+                        ; add BYTE [rbx + shift], al
+                    );
+                }
+                Token::AddTo(to, _) => {
+                    dynasm!(ops
+                        ; add BYTE [rbx + to], cl
+                    );
+                }
+                Token::Clear(shift) => {
+                    dynasm!(ops
+                        ; mov BYTE [rbx + shift], 0
+                    );
+                }
+                Token::Set(n, shift) => {
+                    dynasm!(ops
+                        ; mov BYTE [rbx + shift], n as _
+                    );
+                }
+                Token::Shift(shift) => {
+                    dynasm!(ops
+                        ; add rbx, shift
+                    );
+                }
+                Token::LoopBegin(_) => {
+                    let backward_label = ops.new_dynamic_label();
+                    let forward_label = ops.new_dynamic_label();
+                    labels.push((backward_label, forward_label));
+                    dynasm!(ops
+                        ; cmp BYTE [rbx], 0
+                        ; jz =>forward_label
+                        ;=>backward_label
+                    );
+                    count(&mut ops, 8, 1);
+                }
+                Token::LoopEnd(_) => {
+                    let (backward_label, forward_label) = labels.pop().unwrap();
+                    dynasm!(ops
+                        ; cmp BYTE [rbx], 0
+                        ; jnz =>backward_label
+                        ;=>forward_label
+                    );
+                }
+                Token::Scan(n) => {
+                    dynasm!(ops
+                        ; cmp BYTE [rbx], 0
+                        ; jz >done
+                        ; scan:
+                        ; add rbx, n
+                    );
+                    check(&mut ops, 0);
+                    dynasm!(ops
+                        ; cmp BYTE [rbx], 0
+                        ; jnz <scan
+                        ; done:
+                    );
+                }
+                Token::Input(shift) => {
+                    count(&mut ops, 16, 1);
+                    dynasm!(ops
+                        ; mov rcx, r12
+                        ; mov edx, (config.eof == EofPolicy::MinusOne) as _
+                        ; mov rax, QWORD Self::getchar as *const () as _
+                        ; call rax
+                        ; bt rax, 33
+                        ; jc ->io_failed
+                        ; mov [rbx + shift], al
+                    );
+                }
+                Token::Output(shift) => {
+                    count(&mut ops, 24, 1);
+                    dynasm!(ops
+                        ; movzx ecx, BYTE [rbx + shift]
+                        ; mov rdx, r13
+                        ; mov rax, QWORD putchar as _
+                        ; call rax
+                        ; bt rax, 33
+                        ; jc ->io_failed
+                    );
+                }
+                Token::OutputBytes(ref literal) => {
+                    let flush = literal.iter().any(|&byte| config.flush.should_flush(byte));
+                    let label = ops.new_dynamic_label();
+                    count(&mut ops, 24, literal.len() as i32);
+                    dynasm!(ops
+                        ; lea rcx, [=>label]
+                        ; mov rdx, QWORD literal.len() as _
+                        ; mov r8, r13
+                        ; mov r9d, flush as _
+                        ; mov rax, QWORD Self::putbytes as *const () as _
+                        ; call rax
+                        ; bt rax, 33
+                        ; jc ->io_failed
+                    );
+                    literals.push((label, literal.clone()));
+                }
+                Token::Nop => {}
+                Token::End if config.reset_pointer => {
+                    dynasm!(ops
+                        ; xor eax, eax
+                    );
+                    Self::epilogue(&mut ops, checked, io);
+                }
+                Token::End => {
+                    dynasm!(ops
+                        ; mov rax, rbx
+                    );
+                    if checked {
+                        dynasm!(ops
+                            ; sub rax, r14
+                        );
+                    } else if io {
+                        dynasm!(ops
+                            ; sub rax, [rsp + 0x20]
+                        );
+                    } else {
+                        dynasm!(ops
+                            ; sub rax, r9
+                        );
+                    }
+                    dynasm!(ops
+                        ; mov eax, eax
+                    );
+                    Self::epilogue(&mut ops, checked, io);
+                }
+            }
+            // A token storing to the counter cell it read leaves `ecx` stale.
+            if let Token::Mul(_, shift, base) | Token::AddTo(shift, base) = self.inst[i] {
+                dynasm!(ops
+                    ; untouched:
+                );
+                if shift == base {
+                    loaded = None;
+                }
+            }
+        }
+        if checked {
+            dynasm!(ops
+                ;->out_of_bounds:
+                ; sub rax, r14
+                ; mov eax, eax
+                ; bts rax, 32
+            );
+            Self::epilogue(&mut ops, true, io);
+        }
+        if io {
+            // `rax` holds the callback's result, with bit 33 set.
+            dynasm!(ops
+                ;->io_failed:
+            );
+            Self::epilogue(&mut ops, checked, io);
+        }
+        let end = ops.offset();
+        for (label, literal) in literals {
+            dynasm!(ops
+                ; =>label
+                ; .bytes literal
+            );
+        }
+        let buf = ops.finalize().unwrap();
+        debug_assert!(!buf.is_empty(), "JIT produced no code.");
+        debug_assert!(start.0 < end.0, "JIT entry point is outside the code.");
+        #[cfg(feature = "jit-check")]
+        {
+            // Every program opens with `push rbp` and its closing `End` emits `ret`.
+            assert_eq!(buf[start.0], 0x55, "JIT code does not begin with the prologue.");
+            assert_eq!(buf[end.0 - 1], 0xc3, "JIT code does not end with `ret`.");
+        }
+        #[cfg(feature = "perf-map")]
+        Self::perf_map(&buf[..end.0]);
+        Assembled {
+            buf,
+            entry: start,
+            c_entry,
+            eof: config.eof,
+        }
+    }
+
+    /// Appends the code to `/tmp/perf-<pid>.map`, where `perf report` looks up symbols
+    /// for JIT code. Each program is named `bf_program_<n>` in the order compiled;
+    /// failing to write the map is ignored.
+    #[cfg(feature = "perf-map")]
+    fn perf_map(code: &[u8]) {
+        use std::{fs::OpenOptions, sync::atomic::{AtomicUsize, Ordering}};
+        static PROGRAMS: AtomicUsize = AtomicUsize::new(0);
+        let n = PROGRAMS.fetch_add(1, Ordering::Relaxed);
+        let path = format!("/tmp/perf-{}.map", std::process::id());
+        if let Ok(mut map) = OpenOptions::new().create(true).append(true).open(path) {
+            let _ = writeln!(map, "{:x} {:x} bf_program_{}", code.as_ptr() as usize, code.len(), n);
+        }
+    }
+
+    fn epilogue(ops: &mut Assembler, checked: bool, io: bool) {
+        if io {
+            dynasm!(ops
+                ; add rsp, 0x28
+            );
+        }
+        if checked {
+            dynasm!(ops
+                ; pop r15
+                ; pop r14
+            );
+        }
+        if io {
+            dynasm!(ops
+                ; pop r13
+                ; pop r12
+            );
+        }
+        dynasm!(ops
+            ; pop rbx
+            ; pop rbp
+            ; ret
+        );
+    }
+
+    /// What a callback returns for `result`: zero, or bit 33 and the error's kind.
+    fn io_status(result: io::Result<()>) -> u64 {
+        match result {
+            Ok(()) => 0,
+            Err(e) => IO_FAILED | kind_code(e.kind()) as u64,
+        }
+    }
+
+    unsafe extern "win64" fn putchar(char: u8, writer: *mut &mut dyn Write) -> u64 {
+        Self::io_status(write_out(&mut **writer, &[char]))
+    }
+
+    unsafe extern "win64" fn putbytes(bytes: *const u8, len: usize, writer: *mut &mut dyn Write, flush: bool) -> u64 {
+        let writer = &mut **writer;
+        let written = write_out(writer, std::slice::from_raw_parts(bytes, len));
+        Self::io_status(written.and_then(|()| if flush { flush_out(writer) } else { Ok(()) }))
+    }
+
+    unsafe extern "win64" fn putchar_flush(char: u8, writer: *mut &mut dyn Write) -> u64 {
+        let writer = &mut **writer;
+        Self::io_status(write_out(writer, &[char]).and_then(|()| flush_out(writer)))
+    }
+
+    unsafe extern "win64" fn putchar_line(char: u8, writer: *mut &mut dyn Write) -> u64 {
+        let writer = &mut **writer;
+        Self::io_status(write_out(writer, &[char]).and_then(|()| if char == b'\n' { flush_out(writer) } else { Ok(()) }))
+    }
+
+    /// The byte read in the low 8 bits, or an I/O failure as from `io_status`; a reader
+    /// that would block fails with `WouldBlock`.
+    unsafe extern "win64" fn getchar(reader: *mut &mut dyn Read, minus_one: bool) -> u64 {
+        let eof = if minus_one { EofPolicy::MinusOne } else { EofPolicy::Zero };
+        match eof.read_byte(&mut **reader) {
+            Ok(byte) => byte as u64,
+            Err(e) => Self::io_status(Err(e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tape::GROWABLE_START;
+
+    fn clears(tokens: &[Token]) -> usize {
+        tokens.iter().filter(|token| matches!(token, Token::Clear(_))).count()
+    }
+
+    #[test]
+    fn clear_of_untouched_cell_is_dropped_on_blank_tape() {
+        let program = Interpreter::new("[-]+.".chars()).unwrap();
+        assert_eq!(clears(program.tokens()), 0);
+        assert_eq!(program.run_bytes(b"").unwrap(), [1]);
+    }
+
+    #[test]
+    fn clear_after_clear_is_dropped_on_any_tape() {
+        let program = Interpreter::new(",[-][-]".chars()).unwrap();
+        assert_eq!(clears(&program.on_any_tape().inst), 1);
+    }
+
+    #[test]
+    fn outputs_fold_from_blank_cells_on_blank_tape() {
+        let program = Interpreter::new("+.+.".chars()).unwrap();
+        assert!(program.tokens().contains(&Token::OutputBytes(vec![1, 2])));
+        assert_eq!(program.run_bytes(b"").unwrap(), [1, 2]);
+    }
+
+    #[test]
+    fn outputs_fold_from_set_cells_on_any_tape() {
+        let program = Interpreter::new("[-]+.+.".chars()).unwrap();
+        assert!(program.on_any_tape().inst.contains(&Token::OutputBytes(vec![1, 2])));
+    }
+
+    #[test]
+    fn outputs_of_seeded_cells_are_not_folded() {
+        let program = Interpreter::new("+.+.".chars()).unwrap();
+        let mut output = Vec::new();
+        program.run_on(Tape::from_cells(&[65]), &mut io::empty(), &mut output).unwrap();
+        assert_eq!(output, [66, 67]);
+    }
+
+    #[test]
+    fn compiled_outputs_follow_seeded_tape() {
+        let program = Interpreter::new("+.+.".chars()).unwrap();
+        let mut output = Vec::new();
+        program.compile_on(Tape::from_cells(&[65]))(&mut io::empty(), &mut output);
+        assert_eq!(output, [66, 67]);
+    }
+
+    #[test]
+    fn compiled_loop_clears_seeded_cell() {
+        let program = Interpreter::new("[->+<]>.".chars()).unwrap();
+        let mut output = Vec::new();
+        program.compile_on(Tape::from_cells(&[5]))(&mut io::empty(), &mut output);
+        assert_eq!(output, [5]);
+    }
+
+    #[test]
+    fn buffer_runs_on_callers_tape() {
+        let program = Interpreter::new("[->+<]".chars()).unwrap();
+        let mut tape = Tape::from_cells(&[5]);
+        program.compile_buffer().execute(&mut tape, &mut io::empty(), &mut io::sink()).unwrap();
+        assert_eq!(tape.cells()[..2], [0, 5]);
+    }
+
+    #[test]
+    fn concat_clears_cell_left_by_first_half() {
+        let program = Interpreter::new("+++".chars()).unwrap().concat(&Interpreter::new("[-]".chars()).unwrap());
+        assert_eq!(program.run_inspect(&mut io::empty(), &mut io::sink()).unwrap().cells()[0], 0);
+    }
+
+    #[test]
+    fn concat_outputs_follow_first_half() {
+        let program = Interpreter::new("+++".chars()).unwrap().concat(&Interpreter::new("+.+.".chars()).unwrap());
+        assert_eq!(program.run_bytes(b"").unwrap(), [4, 5]);
+    }
+
+    #[test]
+    fn concat_on_seeded_tape() {
+        let program = Interpreter::new("+.".chars()).unwrap().concat(&Interpreter::new("+.".chars()).unwrap());
+        let mut output = Vec::new();
+        program.run_on(Tape::from_cells(&[65]), &mut io::empty(), &mut output).unwrap();
+        assert_eq!(output, [66, 67]);
+    }
+
+    #[test]
+    fn token_estimate_is_capped() {
+        assert_eq!(Interpreter::estimated_tokens(400), 100);
+        assert_eq!(Interpreter::estimated_tokens(100 << 20), MAX_RESERVED_TOKENS);
+    }
+
+    #[test]
+    fn with_capacity_parses_like_new() {
+        let src = "++[->+++<]>.<,[-]+.+.";
+        let hinted = Interpreter::with_capacity(src.chars(), src.len()).unwrap();
+        assert_eq!(hinted.tokens(), Interpreter::new(src.chars()).unwrap().tokens());
+    }
+
+    fn endless() -> Interpreter {
+        let mut program = Interpreter::new("+[[[]]]".chars()).unwrap();
+        program.config_mut().max_steps = Some(1000);
+        program
+    }
+
+    #[test]
+    fn execute_honours_step_limit() {
+        let program = endless();
+        let result = program.execute_with_threshold(&mut io::empty(), &mut io::sink(), 0);
+        assert!(matches!(result, Err(ExecError::StepLimit { steps: 1000 })));
+    }
+
+    #[test]
+    fn execute_honours_cancel() {
+        let mut program = Interpreter::new("+[]".chars()).unwrap();
+        let cancel = CancelHandle::new();
+        cancel.cancel();
+        program.config_mut().cancel = Some(cancel);
+        let result = program.execute_with_threshold(&mut io::empty(), &mut io::sink(), 0);
+        assert!(matches!(result, Err(ExecError::Cancelled)));
+    }
+
+    #[test]
+    fn pipeline_stage_honours_step_limit() {
+        let program = endless();
+        let result = crate::pipeline::Pipeline::new().then(&program).run_bytes(b"");
+        assert!(matches!(result, Err(ExecError::StepLimit { steps: 1000 })));
+    }
+
+    #[test]
+    fn collapsed_loop_clears_seeded_cell() {
+        let program = Interpreter::new("[->+<]".chars()).unwrap();
+        let tape = program.run_on(Tape::from_cells(&[5]), &mut io::empty(), &mut io::sink()).unwrap();
+        assert_eq!(tape.cells()[..2], [0, 5]);
+    }
+
+    #[derive(Clone, Default)]
+    struct Shared(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn trace_goes_to_the_debug_writer_not_the_output() {
+        let mut program = Interpreter::new("+.".chars()).unwrap();
+        let debug = Shared::default();
+        program.set_trace(true);
+        program.set_debug_writer(debug.clone());
+        let mut output = Vec::new();
+        program.run(&mut io::empty(), &mut output).unwrap();
+        assert_eq!(output, [1]);
+        let trace = String::from_utf8(debug.0.lock().unwrap().clone()).unwrap();
+        assert!(trace.lines().next().unwrap().contains("pointer=0 cell=0"), "{}", trace);
+        assert_eq!(trace.lines().count(), program.tokens().len());
+    }
+
+    #[test]
+    fn debug_writer_is_silent_without_trace() {
+        let mut program = Interpreter::new("+.".chars()).unwrap();
+        let debug = Shared::default();
+        program.set_debug_writer(debug.clone());
+        program.run(&mut io::empty(), &mut io::sink()).unwrap();
+        assert!(debug.0.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn add_mul_and_add_to_wrap_like_u8_in_both_backends() {
+        let mut seed = 0x2545_f491_4f6c_dd1du64;
+        let mut next = move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed
+        };
+        let edges = [0, 1, -1, 2, -2, 127, 128, -128, -129, 255, 256, -255, -256, 257, 511, i16::MAX, i16::MIN];
+        for round in 0..2000 {
+            let n = match edges.get(round) {
+                Some(&n) => n,
+                None => next() as i16,
+            };
+            let (a, b, c) = (next() as u8, next() as u8, next() as u8);
+            let inst = [
+                Token::Input(0), Token::Input(1), Token::Input(2),
+                Token::Mul(n, 1, 0), Token::AddTo(2, 0), Token::Add(n, 0),
+                Token::Output(0), Token::Output(1), Token::Output(2),
+            ];
+            let product = (b as i32 + a as i32 * n as i32).rem_euclid(256) as u8;
+            let sum = a.wrapping_add(c);
+            let added = (a as i32 + n as i32).rem_euclid(256) as u8;
+            assert_eq!(every_backend(&Interpreter::from_tokens(inst.to_vec()).unwrap(), &[a, b, c]), [added, product, sum], "n={} a={} b={} c={}", n, a, b, c);
+        }
+    }
+
+    #[test]
+    fn multiply_loop_after_shift_clears_its_own_counter() {
+        for k in 0..5 {
+            let src = format!("{},[->++>+++<<]", ">".repeat(k));
+            let program = Interpreter::new(src.chars()).unwrap();
+            let k = k as i32;
+            assert!(program.tokens().contains(&Token::Clear(k)), "{}: {:?}", src, program.tokens());
+            assert!(program.tokens().contains(&Token::Mul(2, k + 1, k)), "{}: {:?}", src, program.tokens());
+            assert!(program.tokens().contains(&Token::Mul(3, k + 2, k)), "{}: {:?}", src, program.tokens());
+            for value in [0u8, 1, 7, 200, 255] {
+                let mut expected = vec![0; k as usize + 3];
+                expected[k as usize + 1] = value.wrapping_mul(2);
+                expected[k as usize + 2] = value.wrapping_mul(3);
+                let tape = program.run_inspect(&mut &[value][..], &mut io::sink()).unwrap();
+                assert_eq!(tape.cells()[..k as usize + 3], expected[..], "{} run on {}", src, value);
+                let mut tape = Tape::new();
+                program.compile_buffer().execute(&mut tape, &mut &[value][..], &mut io::sink()).unwrap();
+                assert_eq!(tape.cells()[..k as usize + 3], expected[..], "{} jit on {}", src, value);
+            }
+        }
+    }
+
+    #[test]
+    fn multiply_loop_after_shift_leaves_neighbours_alone() {
+        let program = Interpreter::new("+>++>,<[->>+++<<]".chars()).unwrap();
+        let tape = program.run_inspect(&mut &[9][..], &mut io::sink()).unwrap();
+        assert_eq!(tape.cells()[..4], [1, 0, 9, 6]);
+        let mut tape = Tape::new();
+        program.compile_buffer().execute(&mut tape, &mut &[9][..], &mut io::sink()).unwrap();
+        assert_eq!(tape.cells()[..4], [1, 0, 9, 6]);
+    }
+
+    #[test]
+    fn unbalanced_token_streams_are_rejected() {
+        let open = Interpreter::from_tokens(vec![Token::LoopBegin(0), Token::Add(1, 0)]);
+        assert_eq!(open.unwrap_err(), "] missing.");
+        let close = Interpreter::from_tokens(vec![Token::Add(1, 0), Token::LoopEnd(0), Token::LoopBegin(0)]);
+        assert_eq!(close.unwrap_err(), "[ missing.");
+    }
+
+    #[test]
+    fn loop_operands_are_relinked() {
+        let program = Interpreter::from_tokens(vec![
+            Token::Input(0), Token::LoopBegin(7), Token::Output(0), Token::Add(-1, 0), Token::LoopEnd(7),
+        ]).unwrap();
+        assert_eq!(program.tokens()[1], Token::LoopBegin(4));
+        assert_eq!(program.tokens()[4], Token::LoopEnd(-2));
+        assert_eq!(program.run_bytes(&[3]).unwrap(), [3, 2, 1]);
+    }
+
+    #[test]
+    fn output_left_of_the_pointer() {
+        let program = Interpreter::new(",>>+[<<.>>-]".chars()).unwrap();
+        assert!(program.tokens().contains(&Token::Output(-2)), "{:?}", program.tokens());
+        assert_eq!(program.run_bytes(b"A").unwrap(), b"A");
+        let mut jit = Vec::new();
+        program.compile()(&mut &b"A"[..], &mut jit);
+        assert_eq!(jit, b"A");
+    }
+
+    #[test]
+    fn io_left_of_cell_zero_is_out_of_bounds() {
+        for src in ["+[<.>-]", "+[<,>-]"] {
+            let program = Interpreter::new(src.chars()).unwrap();
+            assert!(program.tokens().iter().any(|token| matches!(token, Token::Output(-1) | Token::Input(-1))), "{:?}", program.tokens());
+            assert_eq!(program.run_bytes(b"x"), Err(ExecError::OutOfBounds { pos: -1 }), "{}", src);
+        }
+    }
+
+    #[test]
+    fn clear_then_add_is_one_set() {
+        let program = Interpreter::new(",[-]+++.".chars()).unwrap();
+        assert_eq!(program.tokens(), [Token::Input(0), Token::Set(3, 0), Token::Output(0), Token::End]);
+        assert_eq!(program.run_bytes(&[9]).unwrap(), [3]);
+        let mut jit = Vec::new();
+        program.compile()(&mut &[9][..], &mut jit);
+        assert_eq!(jit, [3]);
+    }
+
+    #[test]
+    fn set_fuses_past_other_cells_but_not_io() {
+        let program = Interpreter::new(",>,<[-]>[-]<++>+<[.>.<[-]]".chars()).unwrap();
+        assert!(program.tokens().starts_with(&[Token::Input(0), Token::Input(1), Token::Set(2, 0), Token::Set(1, 1)]), "{:?}", program.tokens());
+        assert_eq!(program.run_bytes(&[7, 7]).unwrap(), [2, 1]);
+        let program = Interpreter::new(",[-].+.".chars()).unwrap();
+        assert!(!program.tokens().iter().any(|token| matches!(token, Token::Set(..))), "{:?}", program.tokens());
+        assert_eq!(program.run_bytes(&[7]).unwrap(), [0, 1]);
+    }
+
+    /// The output of `run`, the JIT and the threaded backend for `program` on `input`,
+    /// which must agree.
+    fn every_backend(program: &Interpreter, input: &[u8]) -> Vec<u8> {
+        let run = program.run_bytes(input).unwrap();
+        let mut jit = Vec::new();
+        program.compile()(&mut &input[..], &mut jit);
+        assert_eq!(run, jit, "jit: {:?} on {:?}", program.tokens(), input);
+        let mut threaded = Vec::new();
+        program.threaded().unwrap().run(&mut &input[..], &mut threaded).unwrap();
+        assert_eq!(run, threaded, "threaded: {:?} on {:?}", program.tokens(), input);
+        run
+    }
+
+    #[test]
+    fn saturating_adds_clamp_at_both_ends() {
+        let program = Interpreter::saturating("-.+.".chars()).unwrap();
+        assert_eq!(every_backend(&program, b""), [0, 1]);
+        let program = Interpreter::saturating(format!("{}.+++.--.", "+".repeat(255)).chars()).unwrap();
+        assert_eq!(every_backend(&program, b""), [255, 255, 253]);
+        let program = Interpreter::saturating(",+.,-.".chars()).unwrap();
+        assert_eq!(every_backend(&program, &[255, 0]), [255, 0]);
+    }
+
+    #[test]
+    fn saturating_mul_and_add_to_clamp_at_both_ends() {
+        for (n, base, cell, expected) in [(3, 100, 10, 255), (-3, 100, 10, 0), (2, 5, 250, 255), (-1, 5, 3, 0), (2, 5, 3, 13), (-2, 5, 30, 20)] {
+            let mut program = Interpreter::from_tokens(vec![
+                Token::Input(0), Token::Input(1), Token::Mul(n, 1, 0), Token::Output(1),
+            ]).unwrap();
+            program.config_mut().arith = CellArith::Saturating;
+            assert_eq!(every_backend(&program, &[base, cell]), [expected], "Mul({}) {} {}", n, base, cell);
+        }
+        for (from, to, expected) in [(200, 100, 255), (0, 255, 255), (1, 1, 2)] {
+            let mut program = Interpreter::from_tokens(vec![
+                Token::Input(0), Token::Input(1), Token::AddTo(1, 0), Token::Output(1),
+            ]).unwrap();
+            program.config_mut().arith = CellArith::Saturating;
+            assert_eq!(every_backend(&program, &[from, to]), [expected], "AddTo {} {}", from, to);
+        }
+    }
+
+    #[test]
+    fn saturating_adds_past_a_byte_clamp() {
+        for n in [i16::MIN, -256, -255, -254, 254, 255, 256, i16::MAX] {
+            let mut program = Interpreter::from_tokens(vec![
+                Token::Input(0), Token::Add(n, 0), Token::Output(0),
+            ]).unwrap();
+            program.config_mut().arith = CellArith::Saturating;
+            for start in [0u8, 1, 254, 255] {
+                let expected = (start as i32 + n as i32).clamp(0, 0xff) as u8;
+                assert_eq!(every_backend(&program, &[start]), [expected], "Add({}) on {}", n, start);
+            }
+        }
+    }
+
+    #[test]
+    fn wrapping_stays_the_default() {
+        let program = Interpreter::new("-.".chars()).unwrap();
+        assert_eq!(program.config().arith, CellArith::Wrapping);
+        assert_eq!(every_backend(&program, b""), [255]);
+    }
+
+    #[test]
+    fn million_token_program_compiles_quickly() {
+        let reps = 1_000_000 / 3 + 1;
+        let program = Interpreter::new(",+.".repeat(reps).chars()).unwrap();
+        assert!(program.tokens().len() > 1_000_000);
+        let input: Vec<u8> = (0..reps).map(|i| i as u8).collect();
+        let start = Instant::now();
+        let compiled = program.compile();
+        assert!(start.elapsed() < Duration::from_secs(10), "took {:?}", start.elapsed());
+        let mut output = Vec::new();
+        compiled(&mut &input[..], &mut output);
+        assert!(output.iter().zip(&input).all(|(&out, &byte)| out == byte.wrapping_add(1)));
+        assert_eq!(output.len(), reps);
+    }
+
+    #[test]
+    fn execute_interprets_programs_over_the_jit_limit() {
+        let inst = vec![Token::Output(0); MAX_JIT_TOKENS + 1];
+        let program = Interpreter::from_tokens(inst).unwrap();
+        let mut output = Vec::new();
+        let timings = program.execute_with_threshold(&mut io::empty(), &mut output, 0).unwrap();
+        assert!(timings.compile.is_none());
+        assert_eq!(output.len(), MAX_JIT_TOKENS + 1);
+    }
+
+    #[test]
+    fn growable_tape_grows_up_to_its_limit() {
+        let program = Interpreter::new(">".repeat(GROWABLE_START * 3).chars().chain("+.".chars())).unwrap();
+        let tape = program.run_on(Tape::growable(0x10000), &mut io::empty(), &mut io::sink()).unwrap();
+        assert_eq!(tape.pointer(), GROWABLE_START * 3);
+        assert_eq!(tape.cells()[GROWABLE_START * 3], 1);
+        assert!(tape.cells().len() <= 0x10000);
+        let result = Interpreter::new("+[>+]".chars()).unwrap().run_on(Tape::growable(0x10000), &mut io::empty(), &mut io::sink());
+        assert_eq!(result.unwrap_err(), ExecError::TapeLimitExceeded { limit: 0x10000 });
+    }
+
+    #[test]
+    fn growable_tape_keeps_the_left_end_and_fixed_tapes_fixed() {
+        let left = Interpreter::new("<+".chars()).unwrap().run_on(Tape::growable(0x10000), &mut io::empty(), &mut io::sink());
+        assert_eq!(left.unwrap_err(), ExecError::OutOfBounds { pos: -1 });
+        let fixed = Interpreter::new("+[>+]".chars()).unwrap().run_bytes(b"");
+        assert_eq!(fixed.unwrap_err(), ExecError::OutOfBounds { pos: TAPE_SIZE as i32 });
+    }
+
+    #[test]
+    fn scans_grow_the_tape() {
+        let mut tape = Tape::growable(0x10000);
+        tape.cells_mut().fill(1);
+        let program = Interpreter::new("[>]+.".chars()).unwrap();
+        let mut output = Vec::new();
+        let tape = program.run_on(tape, &mut io::empty(), &mut output).unwrap();
+        assert_eq!(output, [1]);
+        assert_eq!(tape.pointer(), GROWABLE_START);
+        assert_eq!(tape.cells().len(), GROWABLE_START * 2);
+    }
+
+    #[test]
+    fn execute_interprets_on_a_growable_tape() {
+        let mut program = Interpreter::new("+[>+]".chars()).unwrap();
+        program.config_mut().tape = Some(Tape::growable(0x10000));
+        assert!(program.config().needs_interpreter());
+        let result = program.execute_with_threshold(&mut io::empty(), &mut io::sink(), 0);
+        assert_eq!(result.unwrap_err(), ExecError::TapeLimitExceeded { limit: 0x10000 });
+    }
+
+    #[test]
+    fn collapsed_loops_with_a_zero_counter_leave_off_tape_targets_alone() {
+        let program = Interpreter::new(",[-<+>][-<<++>>]+.".chars()).unwrap();
+        assert!(program.tokens().contains(&Token::AddTo(-1, 0)), "{:?}", program.tokens());
+        assert!(program.tokens().contains(&Token::Mul(2, -2, 0)), "{:?}", program.tokens());
+        assert_eq!(every_backend(&program, b"\0"), [1]);
+        assert_eq!(program.compile_checked()(&mut &b"\0"[..], &mut Vec::new()), Ok(()));
+        assert_eq!(program.run_bytes(b"\x01"), Err(ExecError::OutOfBounds { pos: -1 }));
+    }
 }
\ No newline at end of file
@@ -0,0 +1,924 @@
+use std::{
+    collections::VecDeque,
+    fmt,
+    io::{self, BufRead, Read, Write},
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    thread,
+    time::Duration,
+};
+
+/// Waits before retrying a write that would block, spinning briefly and then sleeping
+/// for twice as long each time, up to a millisecond.
+struct Backoff(u32);
+
+impl Backoff {
+    fn wait(&mut self) {
+        if self.0 < 8 {
+            thread::yield_now();
+        } else {
+            thread::sleep(Duration::from_micros(1 << (self.0 - 8).min(10)));
+        }
+        self.0 += 1;
+    }
+}
+
+/// `write_all` for the backends' output, which also waits out `WouldBlock` from a
+/// non-blocking sink instead of failing.
+pub(crate) fn write_out(writer: &mut dyn Write, mut bytes: &[u8]) -> io::Result<()> {
+    let mut backoff = Backoff(0);
+    while !bytes.is_empty() {
+        match writer.write(bytes) {
+            Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+            Ok(n) => {
+                bytes = &bytes[n..];
+                backoff = Backoff(0);
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => backoff.wait(),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// `flush`, waiting out `WouldBlock` like `write_out`.
+pub(crate) fn flush_out(writer: &mut dyn Write) -> io::Result<()> {
+    let mut backoff = Backoff(0);
+    loop {
+        match writer.flush() {
+            Err(e) if matches!(e.kind(), io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock) => backoff.wait(),
+            result => return result,
+        }
+    }
+}
+
+/// The `io::ErrorKind`s that JIT callbacks report by their index here. Any other kind
+/// is reported as `Other`, at index 0.
+const KINDS: [io::ErrorKind; 12] = [
+    io::ErrorKind::Other,
+    io::ErrorKind::NotFound,
+    io::ErrorKind::PermissionDenied,
+    io::ErrorKind::ConnectionReset,
+    io::ErrorKind::ConnectionAborted,
+    io::ErrorKind::NotConnected,
+    io::ErrorKind::BrokenPipe,
+    io::ErrorKind::WouldBlock,
+    io::ErrorKind::InvalidInput,
+    io::ErrorKind::InvalidData,
+    io::ErrorKind::TimedOut,
+    io::ErrorKind::WriteZero,
+];
+
+pub(crate) fn kind_code(kind: io::ErrorKind) -> u32 {
+    KINDS.iter().position(|&known| known == kind).unwrap_or(0) as u32
+}
+
+pub(crate) fn code_kind(code: u32) -> io::ErrorKind {
+    KINDS.get(code as usize).copied().unwrap_or(io::ErrorKind::Other)
+}
+
+/// When the backends flush the writer after emitting a byte. The writer is always
+/// flushed once more when the program ends.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FlushPolicy {
+    #[default]
+    PerChar,
+    PerNewline,
+    AtEnd,
+}
+
+impl FlushPolicy {
+    pub(crate) fn should_flush(self, byte: u8) -> bool {
+        match self {
+            FlushPolicy::PerChar => true,
+            FlushPolicy::PerNewline => byte == b'\n',
+            FlushPolicy::AtEnd => false,
+        }
+    }
+}
+
+/// What a `,` stores once the input is exhausted, i.e. when `read` returns no bytes.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum EofPolicy {
+    #[default]
+    Zero,
+    /// 255, for programs written against `getchar`'s `EOF == -1`.
+    MinusOne,
+    /// Zero, and the reader is never asked again, so the input acts as padded with
+    /// zeros even if the reader would have produced more later, as a terminal does
+    /// after `^D`.
+    InfiniteZeros,
+}
+
+impl EofPolicy {
+    /// Reads one byte from `reader`, retrying interrupted reads. `None` means the reader
+    /// returned `WouldBlock`.
+    pub(crate) fn read(self, reader: &mut dyn Read) -> Option<u8> {
+        match self.read_byte(reader) {
+            Ok(byte) => Some(byte),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => None,
+            Err(e) => panic!("failed to read input: {}", e),
+        }
+    }
+
+    /// `read`, returning any error but `Interrupted`, `WouldBlock` included.
+    pub(crate) fn read_byte(self, reader: &mut dyn Read) -> io::Result<u8> {
+        let mut buf = [0u8];
+        loop {
+            match reader.read(&mut buf) {
+                Ok(0) => return Ok(self.value()),
+                Ok(_) => return Ok(buf[0]),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    pub(crate) fn value(self) -> u8 {
+        match self {
+            EofPolicy::Zero | EofPolicy::InfiniteZeros => 0,
+            EofPolicy::MinusOne => 0xff,
+        }
+    }
+
+    /// Calls `f` with `reader`, wrapped in `Fused` under `InfiniteZeros`.
+    pub(crate) fn with_reader<T>(self, reader: &mut dyn Read, f: impl FnOnce(&mut dyn Read) -> T) -> T {
+        match self {
+            EofPolicy::InfiniteZeros => f(&mut Fused::new(reader)),
+            _ => f(reader),
+        }
+    }
+}
+
+/// Where the interpreter writes its trace, kept apart from the program's own output.
+/// Clones share the same writer, so a config can be cloned freely. Defaults to stderr.
+#[derive(Clone)]
+pub struct DebugWriter(Arc<Mutex<dyn Write + Send>>);
+
+impl DebugWriter {
+    pub fn new(writer: impl Write + Send + 'static) -> Self {
+        Self(Arc::new(Mutex::new(writer)))
+    }
+
+    pub(crate) fn lock(&self) -> MutexGuard<'_, dyn Write + Send + 'static> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Default for DebugWriter {
+    fn default() -> Self {
+        Self::new(io::stderr())
+    }
+}
+
+impl fmt::Debug for DebugWriter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("DebugWriter")
+    }
+}
+
+/// Reports end of input forever once `inner` has, without reading it again.
+#[derive(Debug)]
+pub struct Fused<R> {
+    inner: R,
+    done: bool,
+}
+
+impl<R: Read> Fused<R> {
+    pub fn new(inner: R) -> Self {
+        Self { inner, done: false }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read> Read for Fused<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.done {
+            return Ok(0);
+        }
+        let n = self.inner.read(buf)?;
+        self.done = n == 0 && !buf.is_empty();
+        Ok(n)
+    }
+}
+
+/// Forwards output to `inner` while remembering only the last `capacity` bytes, so the
+/// tail of a runaway program's output can be inspected after the run.
+#[derive(Debug)]
+pub struct TailWriter<W> {
+    inner: W,
+    tail: VecDeque<u8>,
+    capacity: usize,
+}
+
+impl<W: Write> TailWriter<W> {
+    pub fn new(inner: W, capacity: usize) -> Self {
+        Self {
+            inner,
+            tail: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn tail(&self) -> Vec<u8> {
+        self.tail.iter().copied().collect()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl TailWriter<io::Sink> {
+    /// A tail buffer that drops everything else.
+    pub fn discarding(capacity: usize) -> Self {
+        Self::new(io::sink(), capacity)
+    }
+}
+
+impl<W: Write> Write for TailWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        let kept = &buf[n.saturating_sub(self.capacity)..n];
+        let overflow = (self.tail.len() + kept.len()).saturating_sub(self.capacity);
+        self.tail.drain(..overflow);
+        self.tail.extend(kept);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Forwards output to `inner`, counting the bytes it accepted.
+#[derive(Debug)]
+pub struct CountingWriter<W> {
+    inner: W,
+    written: u64,
+}
+
+impl<W: Write> CountingWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self { inner, written: 0 }
+    }
+
+    pub fn written(&self) -> u64 {
+        self.written
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.written += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Passes input through from `inner` while keeping a copy of every byte the program
+/// consumed, so an interactive session can be replayed later from `recorded`.
+#[derive(Debug)]
+pub struct RecordingReader<R> {
+    inner: R,
+    recorded: Vec<u8>,
+}
+
+impl<R: Read> RecordingReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            recorded: Vec::new(),
+        }
+    }
+
+    pub fn recorded(&self) -> &[u8] {
+        &self.recorded
+    }
+
+    pub fn into_parts(self) -> (R, Vec<u8>) {
+        (self.inner, self.recorded)
+    }
+}
+
+impl<R: Read> Read for RecordingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.recorded.extend_from_slice(&buf[..n]);
+        Ok(n)
+    }
+}
+
+/// Feeds a program its input a whole line at a time: a read blocks until `inner`
+/// yields a complete line (or hits EOF), then the line is handed out from a buffer.
+/// Paired with `FlushPolicy::PerNewline`, prompts are out before the user types.
+#[derive(Debug)]
+pub struct LineReader<R> {
+    inner: R,
+    line: Vec<u8>,
+    consumed: usize,
+}
+
+impl<R: BufRead> LineReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            line: Vec::new(),
+            consumed: 0,
+        }
+    }
+
+    /// The rest of the current line that the program has not read yet.
+    pub fn pending(&self) -> &[u8] {
+        &self.line[self.consumed..]
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: BufRead> Read for LineReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.consumed == self.line.len() {
+            self.line.clear();
+            self.consumed = 0;
+            self.inner.read_until(b'\n', &mut self.line)?;
+        }
+        let n = self.pending().len().min(buf.len());
+        buf[..n].copy_from_slice(&self.line[self.consumed..self.consumed + n]);
+        self.consumed += n;
+        Ok(n)
+    }
+}
+
+/// In-memory program input. Works the same for `run` and a `Compiled` closure, e.g.
+/// `compiled(&mut SliceInput::new(b"abc"), &mut output)`, and reports what is left over.
+#[derive(Debug, Clone)]
+pub struct SliceInput<'a> {
+    bytes: &'a [u8],
+    consumed: usize,
+}
+
+impl<'a> SliceInput<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, consumed: 0 }
+    }
+
+    pub fn consumed(&self) -> usize {
+        self.consumed
+    }
+
+    pub fn remaining(&self) -> &'a [u8] {
+        &self.bytes[self.consumed..]
+    }
+}
+
+impl Read for SliceInput<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.remaining().read(buf)?;
+        self.consumed += n;
+        Ok(n)
+    }
+}
+
+/// Collects program output in memory; the bytes stay owned by the caller, so nothing
+/// has to outlive the run.
+#[derive(Debug, Clone, Default)]
+pub struct VecOutput {
+    bytes: Vec<u8>,
+}
+
+impl VecOutput {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn into_inner(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    /// The output decoded as UTF-8, replacing invalid sequences.
+    pub fn to_string_lossy(&self) -> String {
+        String::from_utf8_lossy(&self.bytes).into_owned()
+    }
+}
+
+impl Write for VecOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.bytes.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Adapts a byte generator into a `Read`. Each `read` pulls at most one byte, so the
+/// closure is called exactly once per `,` the program executes; `None` is EOF.
+pub struct FnReader<F> {
+    next: F,
+}
+
+pub fn from_fn<F: FnMut() -> Option<u8>>(next: F) -> FnReader<F> {
+    FnReader { next }
+}
+
+impl<F: FnMut() -> Option<u8>> Read for FnReader<F> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        match (self.next)() {
+            Some(byte) => {
+                buf[0] = byte;
+                Ok(1)
+            }
+            None => Ok(0),
+        }
+    }
+}
+
+/// Input giving `prefix` first and then whatever `reader` gives, for feeding a program
+/// canned bytes ahead of the real input. Works the same for `run` and compiled code.
+pub fn chain_input<R: Read>(prefix: &[u8], reader: R) -> io::Chain<&[u8], R> {
+    prefix.chain(reader)
+}
+
+/// How `ControlWriter` treats control characters.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum ControlMode {
+    #[default]
+    Raw,
+    /// Drops every control character except `\n` and `\t`.
+    Strip,
+    /// Applies BEL, backspace and carriage return to the current line the way a terminal
+    /// would, and drops the remaining control characters.
+    Interpret,
+}
+
+/// Filters control characters out of a program's output before it reaches `inner`.
+/// In `Interpret` mode the current line is held back until a newline or a flush, so
+/// pair it with `FlushPolicy::PerNewline` or `AtEnd`; a flushed line can no longer be
+/// overwritten.
+#[derive(Debug)]
+pub struct ControlWriter<W> {
+    inner: W,
+    mode: ControlMode,
+    line: Vec<u8>,
+    column: usize,
+    bells: usize,
+}
+
+impl<W: Write> ControlWriter<W> {
+    pub fn new(inner: W, mode: ControlMode) -> Self {
+        Self {
+            inner,
+            mode,
+            line: Vec::new(),
+            column: 0,
+            bells: 0,
+        }
+    }
+
+    /// How many BEL characters were swallowed in `Interpret` mode.
+    pub fn bells(&self) -> usize {
+        self.bells
+    }
+
+    pub fn into_inner(mut self) -> io::Result<W> {
+        self.write_line()?;
+        Ok(self.inner)
+    }
+
+    fn write_line(&mut self) -> io::Result<()> {
+        self.inner.write_all(&self.line)?;
+        self.line.clear();
+        self.column = 0;
+        Ok(())
+    }
+
+    fn interpret(&mut self, byte: u8) -> io::Result<()> {
+        match byte {
+            b'\n' => {
+                self.write_line()?;
+                self.inner.write_all(b"\n")?;
+            }
+            0x07 => self.bells += 1,
+            0x08 => self.column = self.column.saturating_sub(1),
+            b'\r' => self.column = 0,
+            b'\t' | 0x20..=0x7e | 0x80..=0xff => {
+                if self.column < self.line.len() {
+                    self.line[self.column] = byte;
+                } else {
+                    self.line.push(byte);
+                }
+                self.column += 1;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for ControlWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.mode {
+            ControlMode::Raw => return self.inner.write(buf),
+            ControlMode::Strip => {
+                let kept: Vec<u8> = buf.iter()
+                    .copied()
+                    .filter(|&b| b == b'\n' || b == b'\t' || !b.is_ascii_control())
+                    .collect();
+                self.inner.write_all(&kept)?;
+            }
+            ControlMode::Interpret => for &byte in buf {
+                self.interpret(byte)?;
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_line()?;
+        self.inner.flush()
+    }
+}
+
+/// Shapes output to exactly `len` bytes for golden-file comparisons: anything past
+/// `len` is dropped, and `into_inner` pads a shorter output with `fill`.
+#[derive(Debug)]
+pub struct FixedLengthWriter<W> {
+    inner: W,
+    len: usize,
+    fill: u8,
+    written: usize,
+}
+
+impl<W: Write> FixedLengthWriter<W> {
+    pub fn new(inner: W, len: usize, fill: u8) -> Self {
+        Self {
+            inner,
+            len,
+            fill,
+            written: 0,
+        }
+    }
+
+    /// How many bytes the program emitted, including truncated ones.
+    pub fn written(&self) -> usize {
+        self.written
+    }
+
+    pub fn into_inner(mut self) -> io::Result<W> {
+        let padding = self.len.saturating_sub(self.written);
+        self.inner.write_all(&vec![self.fill; padding])?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for FixedLengthWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let kept = self.len.saturating_sub(self.written).min(buf.len());
+        self.inner.write_all(&buf[..kept])?;
+        self.written += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Discards output without a syscall, counting the bytes, for timing a program's CPU
+/// work alone, as `benches/mandelbrot.rs` does.
+#[derive(Debug, Default)]
+pub struct NullWriter {
+    written: u64,
+}
+
+impl NullWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn written(&self) -> u64 {
+        self.written
+    }
+}
+
+impl Write for NullWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.written += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Input that never ends: every read fills the buffer with zeros and returns its full
+/// length.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct Zeros;
+
+impl Read for Zeros {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        buf.fill(0);
+        Ok(buf.len())
+    }
+}
+
+/// Input that repeats `bytes` forever, or reads as EOF at once if `bytes` is empty.
+#[derive(Debug, Clone)]
+pub struct Cycle<'a> {
+    bytes: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Cycle<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, at: 0 }
+    }
+}
+
+impl Read for Cycle<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.bytes.is_empty() {
+            return Ok(0);
+        }
+        for byte in buf.iter_mut() {
+            *byte = self.bytes[self.at];
+            self.at = (self.at + 1) % self.bytes.len();
+        }
+        Ok(buf.len())
+    }
+}
+
+/// Input of pseudo-random bytes that are the same for every run with the same seed, for
+/// reproducible runs of programs that take `,` as a source of randomness. A fixed clock
+/// or any other made-up source fits `from_fn` instead.
+#[derive(Debug, Clone)]
+pub struct SeededInput {
+    state: u64,
+    // Bytes of the last number not handed out yet, so the stream does not depend on how
+    // much each `read` asks for.
+    word: [u8; 8],
+    used: usize,
+}
+
+impl SeededInput {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed, word: [0; 8], used: 8 }
+    }
+
+    fn next(&mut self) -> u64 {
+        // splitmix64
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+impl Read for SeededInput {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        for byte in buf.iter_mut() {
+            if self.used == 8 {
+                self.word = self.next().to_le_bytes();
+                self.used = 0;
+            }
+            *byte = self.word[self.used];
+            self.used += 1;
+        }
+        Ok(buf.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{error::ExecError, interpreter::Interpreter};
+
+    /// Takes at most 3 bytes per write and refuses every other call with `WouldBlock`,
+    /// like a non-blocking pipe that a slow reader drains.
+    #[derive(Default)]
+    struct Stingy {
+        bytes: Vec<u8>,
+        calls: usize,
+        flushes: usize,
+    }
+
+    impl Write for Stingy {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.calls += 1;
+            if self.calls.is_multiple_of(2) {
+                return Err(io::ErrorKind::WouldBlock.into());
+            }
+            let n = buf.len().min(3);
+            self.bytes.extend_from_slice(&buf[..n]);
+            Ok(n)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.flushes += 1;
+            if !self.flushes.is_multiple_of(2) {
+                return Err(io::ErrorKind::WouldBlock.into());
+            }
+            Ok(())
+        }
+    }
+
+    const HELLO: &str = "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.";
+
+    #[test]
+    fn write_out_finishes_partial_and_blocked_writes() {
+        let mut writer = Stingy::default();
+        write_out(&mut writer, b"hello, world").unwrap();
+        assert_eq!(writer.bytes, b"hello, world");
+        assert!(writer.calls > 4);
+    }
+
+    #[test]
+    fn flush_out_waits_out_would_block() {
+        let mut writer = Stingy::default();
+        flush_out(&mut writer).unwrap();
+        assert_eq!(writer.flushes, 2);
+    }
+
+    #[test]
+    fn write_out_fails_on_write_zero() {
+        struct Full;
+        impl Write for Full {
+            fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+                Ok(0)
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+        assert_eq!(write_out(&mut Full, b"x").unwrap_err().kind(), io::ErrorKind::WriteZero);
+    }
+
+    #[test]
+    fn every_backend_copes_with_a_slow_sink() {
+        let program = Interpreter::new(HELLO.chars()).unwrap();
+        let mut run = Stingy::default();
+        program.run(&mut io::empty(), &mut run).unwrap();
+        assert_eq!(run.bytes, b"Hello World!\n");
+        let mut jit = Stingy::default();
+        program.compile()(&mut io::empty(), &mut jit);
+        assert_eq!(jit.bytes, b"Hello World!\n");
+        let mut threaded = Stingy::default();
+        program.threaded().unwrap().run(&mut io::empty(), &mut threaded).unwrap();
+        assert_eq!(threaded.bytes, b"Hello World!\n");
+    }
+
+    struct Failing(io::ErrorKind);
+
+    impl Read for Failing {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            Err(self.0.into())
+        }
+    }
+
+    impl Write for Failing {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(self.0.into())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn jit_returns_failed_reads_and_writes() {
+        for flush in [FlushPolicy::PerChar, FlushPolicy::PerNewline, FlushPolicy::AtEnd] {
+            // Output of a cell, and of a literal folded from a blank tape.
+            for src in [",.", "+.+."] {
+                let mut program = Interpreter::new(src.chars()).unwrap();
+                program.set_flush_policy(flush);
+                let result = program.compile_checked()(&mut &b"a"[..], &mut Failing(io::ErrorKind::BrokenPipe));
+                assert_eq!(result, Err(ExecError::Io { kind: io::ErrorKind::BrokenPipe }), "{}", src);
+            }
+        }
+        let program = Interpreter::new(",.".chars()).unwrap();
+        for kind in [io::ErrorKind::WouldBlock, io::ErrorKind::ConnectionReset, io::ErrorKind::Unsupported] {
+            let mut output = Vec::new();
+            let result = program.compile_checked()(&mut Failing(kind), &mut output);
+            let kind = if kind == io::ErrorKind::Unsupported { io::ErrorKind::Other } else { kind };
+            assert_eq!(result, Err(ExecError::Io { kind }));
+            assert!(output.is_empty());
+        }
+    }
+
+    fn every_backend(program: &Interpreter, input: &[u8]) -> Vec<u8> {
+        let run = program.run_bytes(input).unwrap();
+        let mut jit = Vec::new();
+        program.compile()(&mut &input[..], &mut jit);
+        let mut threaded = Vec::new();
+        program.threaded().unwrap().run(&mut &input[..], &mut threaded).unwrap();
+        assert_eq!(run, jit);
+        assert_eq!(run, threaded);
+        run
+    }
+
+    #[test]
+    fn minus_one_cat_stops_at_eof() {
+        let mut program = Interpreter::new(",+[-.,+]".chars()).unwrap();
+        program.set_eof_policy(EofPolicy::MinusOne);
+        assert_eq!(every_backend(&program, b"cat\0me"), b"cat\0me");
+        assert_eq!(every_backend(&program, b""), b"");
+    }
+
+    #[test]
+    fn zero_cat_stops_at_eof() {
+        let program = Interpreter::new(",[.,]".chars()).unwrap();
+        assert_eq!(every_backend(&program, b"cat"), b"cat");
+    }
+
+    #[test]
+    fn eof_values() {
+        assert_eq!(EofPolicy::Zero.read(&mut io::empty()), Some(0));
+        assert_eq!(EofPolicy::MinusOne.read(&mut io::empty()), Some(0xff));
+        assert_eq!(EofPolicy::InfiniteZeros.read(&mut io::empty()), Some(0));
+        assert_eq!(EofPolicy::MinusOne.read(&mut &b"\xff"[..]), Some(0xff));
+    }
+
+    /// Ends once, then has more, like a terminal after `^D`.
+    struct Terminal(Vec<&'static [u8]>);
+
+    impl Read for Terminal {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let chunk = if self.0.is_empty() { &[][..] } else { self.0.remove(0) };
+            buf[..chunk.len()].copy_from_slice(chunk);
+            Ok(chunk.len())
+        }
+    }
+
+    #[test]
+    fn infinite_zeros_never_reads_past_eof() {
+        let mut program = Interpreter::new(",.,.,.".chars()).unwrap();
+        let mut output = Vec::new();
+        program.run(&mut Terminal(vec![b"a", b"", b"b"]), &mut output).unwrap();
+        assert_eq!(output, b"a\0b");
+        program.set_eof_policy(EofPolicy::InfiniteZeros);
+        let mut output = Vec::new();
+        program.run(&mut Terminal(vec![b"a", b"", b"b"]), &mut output).unwrap();
+        assert_eq!(output, b"a\0\0");
+    }
+
+    #[test]
+    fn infinite_zeros_latch_in_the_jit_and_threaded_backends() {
+        let mut program = Interpreter::new(",.,.,.".chars()).unwrap();
+        for (eof, expected) in [(EofPolicy::Zero, b"a\0b"), (EofPolicy::InfiniteZeros, b"a\0\0")] {
+            program.set_eof_policy(eof);
+            let mut jit = Vec::new();
+            program.compile()(&mut Terminal(vec![b"a", b"", b"b"]), &mut jit);
+            assert_eq!(jit, expected, "{:?}", eof);
+            let mut threaded = Vec::new();
+            program.threaded().unwrap().run(&mut Terminal(vec![b"a", b"", b"b"]), &mut threaded).unwrap();
+            assert_eq!(threaded, expected, "{:?}", eof);
+        }
+    }
+
+    #[test]
+    fn fused_reader_stays_at_eof() {
+        let mut reader = Fused::new(Terminal(vec![b"ab", b"", b"cd"]));
+        let mut buf = [0; 4];
+        assert_eq!(reader.read(&mut buf).unwrap(), 2);
+        assert_eq!(reader.read(&mut buf).unwrap(), 0);
+        assert_eq!(reader.read(&mut buf).unwrap(), 0);
+        assert_eq!(reader.into_inner().0, [b"cd"]);
+    }
+
+    #[test]
+    fn fused_reader_ignores_empty_reads_into_empty_buffers() {
+        let mut reader = Fused::new(&b"ab"[..]);
+        assert_eq!(reader.read(&mut []).unwrap(), 0);
+        let mut buf = [0; 2];
+        assert_eq!(reader.read(&mut buf).unwrap(), 2);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,52 @@
+use crate::interpreter::{Interpreter, Token};
+
+/// The optimized program as a tree for backends outside this crate: loops hold their
+/// bodies instead of jump offsets. Offsets are relative to the pointer, which only
+/// `Move` and `Scan` change, and cells wrap modulo 256.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum IrOp {
+    Add { offset: i32, amount: i16 },
+    /// Adds `factor` times the cell at `base` to the cell at `offset`, which is not
+    /// touched, and may be off the tape, while `base` is zero.
+    MulAdd { offset: i32, base: i32, factor: i16 },
+    Clear { offset: i32 },
+    Set { offset: i32, value: u8 },
+    Move { by: i32 },
+    /// Moves by `step` until the pointer is on a zero cell.
+    Scan { step: i32 },
+    /// Runs `body` while the current cell is non-zero.
+    Loop { body: Vec<IrOp> },
+    Input { offset: i32 },
+    Output { offset: i32 },
+    OutputBytes { bytes: Vec<u8> },
+}
+
+impl Interpreter {
+    /// The tokens as `IrOp`s; `End` is left implicit.
+    pub fn lower(&self) -> Vec<IrOp> {
+        let mut stack = vec![Vec::new()];
+        for token in self.tokens() {
+            let op = match *token {
+                Token::Add(amount, offset) => IrOp::Add { offset, amount },
+                Token::Mul(factor, offset, base) => IrOp::MulAdd { offset, base, factor },
+                Token::AddTo(offset, base) => IrOp::MulAdd { offset, base, factor: 1 },
+                Token::Clear(offset) => IrOp::Clear { offset },
+                Token::Set(value, offset) => IrOp::Set { offset, value },
+                Token::Shift(by) => IrOp::Move { by },
+                Token::Scan(step) => IrOp::Scan { step },
+                Token::LoopBegin(_) => {
+                    stack.push(Vec::new());
+                    continue;
+                }
+                Token::LoopEnd(_) => IrOp::Loop { body: stack.pop().unwrap() },
+                Token::Input(offset) => IrOp::Input { offset },
+                Token::Output(offset) => IrOp::Output { offset },
+                Token::OutputBytes(ref bytes) => IrOp::OutputBytes { bytes: bytes.clone() },
+                Token::Nop | Token::End => continue,
+            };
+            stack.last_mut().unwrap().push(op);
+        }
+        stack.pop().unwrap()
+    }
+}
@@ -0,0 +1,33 @@
+pub mod bytecode;
+pub mod cancel;
+pub mod codegen;
+pub mod config;
+pub mod dialect;
+pub mod error;
+pub mod ffi;
+pub mod frontend;
+pub mod interpreter;
+pub mod io;
+pub mod ir;
+pub mod listing;
+#[cfg(feature = "llvm")]
+pub mod llvm;
+pub mod locality;
+pub mod loops;
+pub mod machine;
+#[cfg(feature = "async")]
+pub mod nonblocking;
+pub mod parser;
+pub mod pipeline;
+pub mod quine;
+pub mod registry;
+pub mod report;
+pub mod segments;
+pub mod session;
+pub mod tape;
+pub mod testing;
+pub mod threaded;
+pub mod watch;
+
+pub use codegen::gen_print;
+pub use frontend::strip_comments;
@@ -0,0 +1,181 @@
+use std::{fmt::Write, ops::Range};
+use crate::{
+    interpreter::{Interpreter, Token},
+    loops::{LoopKind, LoopNode},
+};
+
+fn moves(n: i32) -> String {
+    (if n < 0 { "<" } else { ">" }).repeat(n.unsigned_abs() as usize)
+}
+
+fn adds(n: i32) -> String {
+    (if n < 0 { "-" } else { "+" }).repeat(n.unsigned_abs() as usize)
+}
+
+/// `bf` run on the cell at `shift`, coming back to where it started.
+fn at(shift: i32, bf: &str) -> String {
+    format!("{}{}{}", moves(shift), bf, moves(-shift))
+}
+
+/// BF doing what `token` does, or nothing for tokens without a plain equivalent.
+fn token_bf(token: &Token) -> String {
+    match *token {
+        Token::Add(n, shift) => at(shift, &adds(n as i32)),
+        Token::Clear(shift) => at(shift, "[-]"),
+        Token::Set(n, shift) => at(shift, &format!("[-]{}", adds(n as i8 as i32))),
+        Token::Shift(n) => moves(n),
+        Token::LoopBegin(_) => String::from("["),
+        Token::LoopEnd(_) => String::from("]"),
+        Token::Scan(n) => format!("[{}]", moves(n)),
+        Token::Input(shift) => at(shift, ","),
+        Token::Output(shift) => at(shift, "."),
+        Token::Mul(..) | Token::AddTo(..) | Token::OutputBytes(_) | Token::Nop | Token::End => String::new(),
+    }
+}
+
+/// The loop a run of `Mul`/`AddTo` tokens and their `Clear` were collapsed from.
+fn collapsed_bf(tokens: &[Token]) -> String {
+    let base = match tokens.last() {
+        Some(&Token::Clear(base)) => base,
+        _ => return String::new(),
+    };
+    let mut body = String::from("-");
+    for token in tokens {
+        match *token {
+            Token::Mul(n, shift, _) => body += &at(shift - base, &adds(n as i32)),
+            Token::AddTo(to, _) => body += &at(to - base, "+"),
+            _ => {}
+        }
+    }
+    at(base, &format!("[{}]", body))
+}
+
+/// The characters each of `inst` came from, given where its loop tokens were written
+/// and the length of the source. A `Scan` spans its loop's brackets, and any other
+/// token the stretch between the brackets around it.
+fn sources(inst: &[Token], brackets: &[usize], len: usize) -> Vec<Range<usize>> {
+    let mut next = 0;
+    let mut start = 0;
+    inst.iter().map(|token| {
+        let bracket = |k: usize| brackets.get(k).copied().unwrap_or(len);
+        match token {
+            Token::LoopBegin(_) | Token::LoopEnd(_) => {
+                let at = bracket(next);
+                next += 1;
+                start = at + 1;
+                at..at + 1
+            }
+            Token::Scan(_) => {
+                let (open, close) = (bracket(next), bracket(next + 1));
+                next += 2;
+                start = close + 1;
+                open..close + 1
+            }
+            _ => start..bracket(next),
+        }
+    }).collect()
+}
+
+impl Interpreter {
+    /// A listing of the optimized program, one token per line: its index, BF with the
+    /// same effect indented by loop depth, and the token. A collapsed loop is rebuilt
+    /// on its first token, and tokens with no BF of their own (see `OutputBytes`)
+    /// leave the column empty. The program does not keep its source, so any `[-]` or
+    /// `>++<` is only the shortest equivalent rather than what was written; see
+    /// `Parser::listing` for one with source positions.
+    pub fn listing(&self) -> String {
+        self.listing_with(None)
+    }
+
+    /// `listing`, with a column of source positions when given the position of every
+    /// loop token's bracket and the length of the source.
+    pub(crate) fn listing_with(&self, source: Option<(&[usize], usize)>) -> String {
+        fn collapsed(nodes: &[LoopNode], out: &mut Vec<Range<usize>>) {
+            for node in nodes {
+                match node.kind {
+                    LoopKind::Copy | LoopKind::Multiply => out.push(node.span.clone()),
+                    _ => collapsed(&node.children, out),
+                }
+            }
+        }
+        let inst = self.tokens();
+        let sources = source.map(|(brackets, len)| sources(inst, brackets, len));
+        let mut spans = Vec::new();
+        collapsed(&self.loop_tree().roots, &mut spans);
+        let mut spans = spans.into_iter().peekable();
+        let mut inside = None;
+        let mut listing = String::new();
+        let mut depth = 0;
+        for (i, token) in inst.iter().enumerate() {
+            if let Token::LoopEnd(_) = token {
+                depth -= 1;
+            }
+            if spans.peek().is_some_and(|span| span.start == i) {
+                inside = spans.next();
+            }
+            let bf = match inside {
+                Some(ref span) if span.start == i => collapsed_bf(&inst[span.clone()]),
+                Some(ref span) if span.contains(&i) => String::new(),
+                _ => token_bf(token),
+            };
+            write!(listing, "{:>6}  {:<32} ", i, format!("{}{}", "  ".repeat(depth), bf)).unwrap();
+            if let Some(ref sources) = sources {
+                write!(listing, "{:<12} ", format!("{}..{}", sources[i].start, sources[i].end)).unwrap();
+            }
+            writeln!(listing, "{:?}", token).unwrap();
+            if let Token::LoopBegin(_) = token {
+                depth += 1;
+            }
+        }
+        listing
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    fn listing(src: &str) -> Vec<String> {
+        let mut parser = Parser::new();
+        parser.feed(src.chars()).unwrap();
+        parser.listing().unwrap().lines().map(String::from).collect()
+    }
+
+    #[test]
+    fn loop_tokens_point_at_their_brackets() {
+        let lines = listing("+[.-]>[>]");
+        assert!(lines[1].contains(" 1..2 ") && lines[1].ends_with("LoopBegin(4)"), "{:?}", lines);
+        assert!(lines[2].contains(" 2..4 ") && lines[2].ends_with("Output(0)"), "{:?}", lines);
+        assert!(lines[4].contains(" 4..5 ") && lines[4].ends_with("LoopEnd(-2)"), "{:?}", lines);
+        assert!(lines[6].contains(" 6..9 ") && lines[6].ends_with("Scan(1)"), "{:?}", lines);
+    }
+
+    #[test]
+    fn straight_line_tokens_span_their_block() {
+        let lines = listing("++>+++[<[-]>-]");
+        assert!(lines[..3].iter().all(|line| line.contains(" 0..6 ")), "{:?}", lines);
+        assert!(lines[4].contains(" 7..13 ") && lines[4].ends_with("Clear(-1)"), "{:?}", lines);
+        assert!(lines.last().unwrap().contains(" 14..14 "), "{:?}", lines);
+    }
+
+    #[test]
+    fn sources_skip_collapsed_loops() {
+        let inst = Interpreter::new("+[-]>[<+>-]".chars()).unwrap();
+        let sources = sources(inst.tokens(), &[], 11);
+        assert!(sources.iter().all(|source| *source == (0..11)));
+    }
+
+    #[test]
+    fn listing_without_source_has_no_positions() {
+        let program = Interpreter::new("[>]".chars()).unwrap();
+        assert_eq!(program.listing().lines().next().unwrap().trim_end(), format!("{:>6}  {:<32} Scan(1)", 0, "[>]"));
+    }
+
+    #[test]
+    fn unclosed_loop_has_no_listing() {
+        let mut parser = Parser::new();
+        parser.feed("+[".chars()).unwrap();
+        assert_eq!(parser.listing(), Err("] missing."));
+    }
+}
\ No newline at end of file
@@ -0,0 +1,166 @@
+use std::path::Path;
+use inkwell::{
+    context::Context,
+    module::Module,
+    passes::{PassManager, PassManagerBuilder},
+    targets::{CodeModel, FileType, InitializationConfig, RelocMode, Target, TargetMachine},
+    IntPredicate,
+    OptimizationLevel,
+};
+use crate::{
+    interpreter::{Interpreter, Token},
+    tape::TAPE_SIZE,
+};
+
+impl Interpreter {
+    /// Lowers the program to textual LLVM IR defining `i32 @main()`, which reads and
+    /// writes through libc's `getchar`/`putchar`. Feed it to `clang` or `llc -O3` to
+    /// build a standalone executable.
+    pub fn to_llvm_ir(&self) -> String {
+        let context = Context::create();
+        let module = self.lower_module(&context);
+        module.print_to_string().to_string()
+    }
+
+    /// Optimizes the lowered module at `-O3` and writes a native object file for the
+    /// host, ready to be linked against libc.
+    pub fn write_object(&self, path: &Path) -> Result<(), String> {
+        Target::initialize_native(&InitializationConfig::default())?;
+        let triple = TargetMachine::get_default_triple();
+        let target = Target::from_triple(&triple).map_err(|e| e.to_string())?;
+        let machine = target.create_target_machine(
+            &triple,
+            &TargetMachine::get_host_cpu_name().to_string(),
+            &TargetMachine::get_host_cpu_features().to_string(),
+            OptimizationLevel::Aggressive,
+            RelocMode::PIC,
+            CodeModel::Default,
+        ).ok_or("cannot create a target machine for the host.")?;
+        let context = Context::create();
+        let module = self.lower_module(&context);
+        let builder = PassManagerBuilder::create();
+        builder.set_optimization_level(OptimizationLevel::Aggressive);
+        let passes = PassManager::create(());
+        builder.populate_module_pass_manager(&passes);
+        passes.run_on(&module);
+        machine.write_to_file(&module, FileType::Object, path).map_err(|e| e.to_string())
+    }
+
+    fn lower_module<'ctx>(&self, context: &'ctx Context) -> Module<'ctx> {
+        let module = context.create_module("bf");
+        let builder = context.create_builder();
+        let i8_type = context.i8_type();
+        let i32_type = context.i32_type();
+        let i64_type = context.i64_type();
+        let getchar = module.add_function("getchar", i32_type.fn_type(&[], false), None);
+        let putchar = module.add_function("putchar", i32_type.fn_type(&[i32_type.into()], false), None);
+        let main = module.add_function("main", i32_type.fn_type(&[], false), None);
+        builder.position_at_end(context.append_basic_block(main, "entry"));
+
+        let size = i64_type.const_int(TAPE_SIZE as u64, false);
+        let tape = builder.build_array_alloca(i8_type, size, "tape").unwrap();
+        builder.build_memset(tape, 1, i8_type.const_zero(), size).unwrap();
+        let pos = builder.build_alloca(i64_type, "pos").unwrap();
+        builder.build_store(pos, i64_type.const_zero()).unwrap();
+        let cell = |shift: i32| {
+            let offset = i64_type.const_int(shift as i64 as u64, true);
+            let pos = builder.build_load(pos, "pos").unwrap().into_int_value();
+            let index = builder.build_int_add(pos, offset, "index").unwrap();
+            unsafe { builder.build_in_bounds_gep(tape, &[index], "cell").unwrap() }
+        };
+        let load = |shift: i32| builder.build_load(cell(shift), "value").unwrap().into_int_value();
+
+        let mut loops = Vec::new();
+        for token in self.tokens() {
+            match *token {
+                Token::Add(n, shift) => {
+                    let sum = builder.build_int_add(load(shift), i8_type.const_int(n as u64, true), "sum").unwrap();
+                    builder.build_store(cell(shift), sum).unwrap();
+                }
+                Token::Mul(_, shift, base) | Token::AddTo(shift, base) => {
+                    let n = match *token {
+                        Token::Mul(n, ..) => n,
+                        _ => 1,
+                    };
+                    // The target is only loaded once the counter is known to be non-zero,
+                    // since it may be off the tape otherwise.
+                    let add = context.append_basic_block(main, "add");
+                    let added = context.append_basic_block(main, "added");
+                    let counter = load(base);
+                    let zero = builder.build_int_compare(IntPredicate::EQ, counter, i8_type.const_zero(), "zero").unwrap();
+                    builder.build_conditional_branch(zero, added, add).unwrap();
+                    builder.position_at_end(add);
+                    let product = builder.build_int_mul(counter, i8_type.const_int(n as u64, true), "product").unwrap();
+                    let sum = builder.build_int_add(load(shift), product, "sum").unwrap();
+                    builder.build_store(cell(shift), sum).unwrap();
+                    builder.build_unconditional_branch(added).unwrap();
+                    builder.position_at_end(added);
+                }
+                Token::Clear(shift) => {
+                    builder.build_store(cell(shift), i8_type.const_zero()).unwrap();
+                }
+                Token::Set(n, shift) => {
+                    builder.build_store(cell(shift), i8_type.const_int(n as u64, false)).unwrap();
+                }
+                Token::Shift(shift) => {
+                    let current = builder.build_load(pos, "pos").unwrap().into_int_value();
+                    let moved = builder.build_int_add(current, i64_type.const_int(shift as i64 as u64, true), "pos").unwrap();
+                    builder.build_store(pos, moved).unwrap();
+                }
+                Token::LoopBegin(_) => {
+                    let header = context.append_basic_block(main, "loop");
+                    let body = context.append_basic_block(main, "body");
+                    let exit = context.append_basic_block(main, "exit");
+                    builder.build_unconditional_branch(header).unwrap();
+                    builder.position_at_end(header);
+                    let zero = builder.build_int_compare(IntPredicate::EQ, load(0), i8_type.const_zero(), "zero").unwrap();
+                    builder.build_conditional_branch(zero, exit, body).unwrap();
+                    builder.position_at_end(body);
+                    loops.push((header, exit));
+                }
+                Token::LoopEnd(_) => {
+                    let (header, exit) = loops.pop().unwrap();
+                    builder.build_unconditional_branch(header).unwrap();
+                    builder.position_at_end(exit);
+                }
+                Token::Scan(n) => {
+                    let header = context.append_basic_block(main, "scan");
+                    let body = context.append_basic_block(main, "step");
+                    let exit = context.append_basic_block(main, "found");
+                    builder.build_unconditional_branch(header).unwrap();
+                    builder.position_at_end(header);
+                    let zero = builder.build_int_compare(IntPredicate::EQ, load(0), i8_type.const_zero(), "zero").unwrap();
+                    builder.build_conditional_branch(zero, exit, body).unwrap();
+                    builder.position_at_end(body);
+                    let current = builder.build_load(pos, "pos").unwrap().into_int_value();
+                    let moved = builder.build_int_add(current, i64_type.const_int(n as i64 as u64, true), "pos").unwrap();
+                    builder.build_store(pos, moved).unwrap();
+                    builder.build_unconditional_branch(header).unwrap();
+                    builder.position_at_end(exit);
+                }
+                Token::Input(shift) => {
+                    let read = builder.build_call(getchar, &[], "read").unwrap()
+                        .try_as_basic_value().left().unwrap().into_int_value();
+                    let eof = builder.build_int_compare(IntPredicate::SLT, read, i32_type.const_zero(), "eof").unwrap();
+                    let byte = builder.build_select(eof, i32_type.const_zero(), read, "byte").unwrap().into_int_value();
+                    let byte = builder.build_int_truncate(byte, i8_type, "byte").unwrap();
+                    builder.build_store(cell(shift), byte).unwrap();
+                }
+                Token::Output(shift) => {
+                    let byte = builder.build_int_z_extend(load(shift), i32_type, "byte").unwrap();
+                    builder.build_call(putchar, &[byte.into()], "").unwrap();
+                }
+                Token::OutputBytes(ref literal) => {
+                    for &byte in literal {
+                        builder.build_call(putchar, &[i32_type.const_int(byte as u64, false).into()], "").unwrap();
+                    }
+                }
+                Token::Nop => {}
+                Token::End => {
+                    builder.build_return(Some(&i32_type.const_zero())).unwrap();
+                }
+            }
+        }
+        module
+    }
+}
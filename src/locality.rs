@@ -0,0 +1,76 @@
+use std::io::{Read, Write};
+use crate::{
+    error::ExecError,
+    interpreter::{Interpreter, Meter, Token},
+    watch::accesses,
+};
+
+/// The cells a run touched, in order, for studying its locality and working set.
+/// Every cell a token reads or writes counts as one access, so offsets folded into
+/// tokens by the optimizer show up as well as pointer moves. Once `positions` is full,
+/// every other sample is dropped and `stride` doubles, keeping it evenly spaced.
+#[derive(Debug, Clone)]
+pub struct PointerTrace {
+    /// How many accesses each sample stands for.
+    pub stride: u64,
+    /// The cell of every `stride`th access, starting with the first.
+    pub positions: Vec<usize>,
+    seen: u64,
+    cap: usize,
+}
+
+impl PointerTrace {
+    fn new(cap: usize) -> Self {
+        Self {
+            stride: 1,
+            positions: Vec::new(),
+            seen: 0,
+            cap: cap.max(2),
+        }
+    }
+
+    /// Notes the cells `token` is about to access from `pos`, skipping any off the tape.
+    pub(crate) fn record(&mut self, token: &Token, pos: i32, len: usize) {
+        let (read, written) = accesses(token);
+        for shift in read.into_iter().chain(written) {
+            let cell = pos + shift;
+            if cell < 0 || cell as usize >= len {
+                continue;
+            }
+            if self.seen.is_multiple_of(self.stride) {
+                if self.positions.len() == self.cap {
+                    let mut i = 0;
+                    self.positions.retain(|_| {
+                        i += 1;
+                        i % 2 == 1
+                    });
+                    self.stride *= 2;
+                }
+                if self.seen.is_multiple_of(self.stride) {
+                    self.positions.push(cell as usize);
+                }
+            }
+            self.seen += 1;
+        }
+    }
+
+    /// How many accesses were made in all, sampled or not.
+    pub fn accesses(&self) -> u64 {
+        self.seen
+    }
+}
+
+impl Interpreter {
+    /// Like `run`, recording a `PointerTrace` of at most `max_len` samples. Only the
+    /// interpreter's inspecting loop records it, so the run is slower than `run`.
+    pub fn run_pointer_trace(&self, max_len: usize, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<PointerTrace, ExecError> {
+        let mut meter = Meter {
+            pointers: Some(PointerTrace::new(max_len)),
+            ..Meter::default()
+        };
+        let config = self.config();
+        let mut tape = config.tape.clone().unwrap_or_default();
+        self.for_tape(config).interpret(config, &mut tape, reader, writer, &mut meter)?;
+        Ok(meter.pointers.unwrap())
+    }
+}
@@ -0,0 +1,75 @@
+use std::ops::Range;
+use crate::interpreter::{Interpreter, Token};
+
+/// What a loop became after optimization.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum LoopKind {
+    /// `[-]`, collapsed into a `Clear`.
+    Clear,
+    /// Collapsed into `AddTo` tokens and a `Clear`.
+    Copy,
+    /// Collapsed into `Mul` (and maybe `AddTo`) tokens and a `Clear`.
+    Multiply,
+    /// A body that only moves the pointer, such as `[>]`, collapsed into a `Scan`.
+    Scan,
+    Generic,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct LoopNode {
+    pub kind: LoopKind,
+    /// Indices into `Interpreter::tokens`: `LoopBegin` through `LoopEnd` for a kept loop,
+    /// or the tokens a collapsed loop was replaced with.
+    pub span: Range<usize>,
+    pub children: Vec<LoopNode>,
+}
+
+/// The loops of a program, outermost first, in source order.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct LoopTree {
+    pub roots: Vec<LoopNode>,
+}
+
+impl Interpreter {
+    /// Reconstructs the loop nesting from the optimized tokens. Collapsed loops are
+    /// recognized by their closing `Clear`, so one later fused into a `Set` is not listed.
+    pub fn loop_tree(&self) -> LoopTree {
+        let inst = self.tokens();
+        let mut stack: Vec<(usize, Vec<LoopNode>)> = vec![(0, Vec::new())];
+        for (i, token) in inst.iter().enumerate() {
+            let node = match *token {
+                Token::LoopBegin(_) => {
+                    stack.push((i, Vec::new()));
+                    continue;
+                }
+                Token::LoopEnd(_) => {
+                    let (begin, children) = stack.pop().unwrap();
+                    LoopNode { kind: LoopKind::Generic, span: begin..i + 1, children }
+                }
+                Token::Scan(_) => LoopNode { kind: LoopKind::Scan, span: i..i + 1, children: Vec::new() },
+                Token::Clear(base) => {
+                    let body = inst[..i].iter()
+                        .rev()
+                        .take_while(|token| matches!(token, Token::Mul(_, _, b) | Token::AddTo(_, b) if *b == base))
+                        .collect::<Vec<_>>();
+                    let kind = if body.is_empty() {
+                        LoopKind::Clear
+                    } else if body.iter().any(|token| matches!(token, Token::Mul(..))) {
+                        LoopKind::Multiply
+                    } else {
+                        LoopKind::Copy
+                    };
+                    LoopNode { kind, span: i - body.len()..i + 1, children: Vec::new() }
+                }
+                _ => continue,
+            };
+            stack.last_mut().unwrap().1.push(node);
+        }
+        LoopTree {
+            roots: stack.pop().unwrap().1,
+        }
+    }
+}
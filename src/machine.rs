@@ -0,0 +1,100 @@
+use std::io::{self, Read, Write};
+use crate::{
+    error::ExecError,
+    interpreter::{Interpreter, Meter},
+    tape::Tape,
+};
+
+/// A program together with a tape that outlives its runs, for using a program as a
+/// kernel: load operands with `set_cell`, `compute`, and read results with `get_cell`.
+#[derive(Debug)]
+pub struct Machine {
+    program: Interpreter,
+    tape: Tape,
+}
+
+impl Machine {
+    /// Starts from the program's configured tape, or a blank one. Runs after the first
+    /// see what earlier ones left, so the program is kept as optimized for any tape.
+    pub fn new(program: Interpreter) -> Self {
+        let tape = program.config().tape.clone().unwrap_or_default();
+        Self {
+            program: program.into_any_tape(),
+            tape,
+        }
+    }
+
+    pub fn program(&self) -> &Interpreter {
+        &self.program
+    }
+
+    pub fn tape(&self) -> &Tape {
+        &self.tape
+    }
+
+    pub fn get_cell(&self, index: usize) -> Option<u8> {
+        self.tape.cells().get(index).copied()
+    }
+
+    pub fn set_cell(&mut self, index: usize, value: u8) -> Result<(), ExecError> {
+        match self.tape.cells_mut().get_mut(index) {
+            Some(cell) => {
+                *cell = value;
+                Ok(())
+            }
+            None => Err(ExecError::OutOfBounds { pos: index as i32 }),
+        }
+    }
+
+    pub fn pointer(&self) -> usize {
+        self.tape.pointer()
+    }
+
+    pub fn set_pointer(&mut self, pointer: usize) {
+        self.tape.set_pointer(pointer);
+    }
+
+    /// Runs the program with the tape as left by the last run, pointer included.
+    pub fn run(&mut self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<(), ExecError> {
+        self.program.interpret(self.program.config(), &mut self.tape, reader, writer, &mut Meter::default())
+    }
+
+    /// Like `run` with no input and discarded output, so only the tape carries data.
+    pub fn compute(&mut self) -> Result<(), ExecError> {
+        self.run(&mut io::empty(), &mut io::sink())
+    }
+
+    /// Puts the tape back to how `new` started it.
+    pub fn reset(&mut self) {
+        self.tape = self.program.config().tape.clone().unwrap_or_default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loop_clears_preloaded_cell() {
+        let mut machine = Machine::new(Interpreter::new("[->+<]".chars()).unwrap());
+        machine.set_cell(0, 5).unwrap();
+        machine.compute().unwrap();
+        assert_eq!((machine.get_cell(0), machine.get_cell(1)), (Some(0), Some(5)));
+    }
+
+    #[test]
+    fn runs_see_earlier_results() {
+        let mut machine = Machine::new(Interpreter::new("+.+.".chars()).unwrap());
+        let mut output = Vec::new();
+        machine.run(&mut io::empty(), &mut output).unwrap();
+        machine.run(&mut io::empty(), &mut output).unwrap();
+        assert_eq!(output, [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn set_cell_outside_tape_fails() {
+        let mut machine = Machine::new(Interpreter::new("".chars()).unwrap());
+        let len = machine.tape().cells().len();
+        assert_eq!(machine.set_cell(len, 1), Err(ExecError::OutOfBounds { pos: len as i32 }));
+    }
+}
@@ -1,26 +1,17 @@
-use crate::interpreter::Interpreter;
+use bf_rs::{interpreter::Interpreter, io::chain_input};
 use std::{
-    fs::File,
-    io::{
-        self,
-        Read,
-    },
-    time::SystemTime,
+    fs,
+    io,
 };
 
-mod interpreter;
-
 fn main() {
-    let mut reader = File::open("./samples/bfbf.bf").expect("Cannot open file");
-    let mut src = String::new();
-    reader.read_to_string(&mut src).expect("Fail to read file");
-    let interpreter = Interpreter::new(src.chars()).unwrap();
+    let src = fs::read("./samples/bfbf.bf").expect("Cannot open file");
+    let interpreter = Interpreter::from_bytes(&src).unwrap();
     println!("{:?}", interpreter);
-    let mut reader = File::open("./samples/bottles.bf").expect("Cannot open file");
-    let mut input = String::new();
-    reader.read_to_string(&mut input).expect("Fail to read file");
-    input.extend("\x0062500\n".chars());
-    let now = SystemTime::now();
-    interpreter.compile()(&input.as_bytes(), &io::stdout());
-    println!("Time cost: {}ms", SystemTime::now().duration_since(now).unwrap().as_millis());
+    let mut program = fs::read("./samples/bottles.bf").expect("Cannot open file");
+    program.push(0);
+    let mut input = chain_input(&program, &b"62500\n"[..]);
+    let timings = interpreter.execute_with_threshold(&mut input, &mut io::stdout(), 0).unwrap();
+    println!("Compile time: {}ms", timings.compile.unwrap_or_default().as_millis());
+    println!("Run time: {}ms", timings.run.as_millis());
 }
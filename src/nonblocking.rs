@@ -0,0 +1,172 @@
+use std::{
+    future::{self, Future},
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use futures_io::{AsyncRead, AsyncWrite};
+use crate::{
+    error::ExecError,
+    interpreter::Interpreter,
+    session::Step,
+};
+
+/// How many tokens `run_async` interprets between writing out what the program printed
+/// and yielding to the executor.
+pub const ASYNC_SLICE: u64 = 1 << 16;
+
+async fn read<R: AsyncRead + Unpin>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    future::poll_fn(|cx| Pin::new(&mut *reader).poll_read(cx, buf)).await
+}
+
+async fn write_all<W: AsyncWrite + Unpin>(writer: &mut W, mut buf: &[u8]) -> io::Result<()> {
+    while !buf.is_empty() {
+        match future::poll_fn(|cx| Pin::new(&mut *writer).poll_write(cx, buf)).await? {
+            0 => return Err(io::ErrorKind::WriteZero.into()),
+            n => buf = &buf[n..],
+        }
+    }
+    future::poll_fn(|cx| Pin::new(&mut *writer).poll_flush(cx)).await
+}
+
+/// Pending once, so the executor gets to run other tasks.
+struct YieldNow(bool);
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.0 {
+            return Poll::Ready(());
+        }
+        self.0 = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+impl Interpreter {
+    /// Interprets the program in a `Session`, awaiting `reader` whenever it runs out of
+    /// input. Every `ASYNC_SLICE` tokens, and whenever the program waits or ends, what
+    /// it printed is written out and flushed; after a full slice the task also yields,
+    /// so a program that never reads still makes way for others and is held back by a
+    /// slow writer. Failed reads and writes end the run with `ExecError::Io`.
+    pub async fn run_async<R, W>(&self, reader: &mut R, writer: &mut W) -> Result<(), ExecError>
+        where R: AsyncRead + Unpin, W: AsyncWrite + Unpin {
+        let mut session = self.session();
+        let mut buf = [0u8; 4096];
+        loop {
+            let step = session.resume_for(ASYNC_SLICE);
+            write_all(writer, &session.take_output()).await?;
+            match step? {
+                Step::Finished => return Ok(()),
+                Step::Yielded => YieldNow(false).await,
+                Step::Output(_) => unreachable!(),
+                Step::NeedsInput => match read(reader, &mut buf).await? {
+                    0 => session.close_input(),
+                    n => session.feed(&buf[..n]),
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{pin::pin, task::Waker};
+    use super::*;
+
+    /// Polls `future` to completion, returning its output and how often it was pending.
+    fn block_on<T>(future: impl Future<Output = T>) -> (T, usize) {
+        let mut future = pin!(future);
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut pending = 0;
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(output) => return (output, pending),
+                Poll::Pending => pending += 1,
+            }
+        }
+    }
+
+    /// Hands out `chunks` one per read, pending before each.
+    struct Chunks {
+        chunks: Vec<io::Result<&'static [u8]>>,
+        ready: bool,
+    }
+
+    impl AsyncRead for Chunks {
+        fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+            self.ready = !self.ready;
+            if self.ready {
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+            if self.chunks.is_empty() {
+                return Poll::Ready(Ok(0));
+            }
+            Poll::Ready(self.chunks.remove(0).map(|chunk| {
+                buf[..chunk.len()].copy_from_slice(chunk);
+                chunk.len()
+            }))
+        }
+    }
+
+    /// Takes up to `limit` bytes, then fails like a closed pipe.
+    struct Pipe {
+        bytes: Vec<u8>,
+        limit: usize,
+    }
+
+    impl AsyncWrite for Pipe {
+        fn poll_write(mut self: Pin<&mut Self>, _: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+            let room = self.limit - self.bytes.len();
+            if room == 0 {
+                return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()));
+            }
+            let n = buf.len().min(room);
+            self.bytes.extend_from_slice(&buf[..n]);
+            Poll::Ready(Ok(n))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    #[test]
+    fn cat_awaits_each_chunk() {
+        let program = Interpreter::new(",[.,]".chars()).unwrap();
+        let mut reader = Chunks { chunks: vec![Ok(b"ab"), Ok(b"c")], ready: false };
+        let mut writer = Pipe { bytes: Vec::new(), limit: usize::MAX };
+        let (result, pending) = block_on(program.run_async(&mut reader, &mut writer));
+        result.unwrap();
+        assert_eq!(writer.bytes, b"abc");
+        assert_eq!(pending, 3);
+    }
+
+    #[test]
+    fn endless_output_yields_and_fails_on_a_closed_pipe() {
+        let program = Interpreter::new("+[.]".chars()).unwrap();
+        let mut reader = Chunks { chunks: Vec::new(), ready: false };
+        let mut writer = Pipe { bytes: Vec::new(), limit: 1 << 20 };
+        let (result, pending) = block_on(program.run_async(&mut reader, &mut writer));
+        assert_eq!(result, Err(ExecError::Io { kind: io::ErrorKind::BrokenPipe }));
+        assert_eq!(writer.bytes.len(), 1 << 20);
+        assert!(writer.bytes.iter().all(|&byte| byte == 1));
+        assert!(pending > 0);
+    }
+
+    #[test]
+    fn failed_read_is_an_error() {
+        let program = Interpreter::new(",.".chars()).unwrap();
+        let mut reader = Chunks { chunks: vec![Err(io::ErrorKind::ConnectionReset.into())], ready: true };
+        let mut writer = Pipe { bytes: Vec::new(), limit: usize::MAX };
+        let (result, _) = block_on(program.run_async(&mut reader, &mut writer));
+        assert_eq!(result, Err(ExecError::Io { kind: io::ErrorKind::ConnectionReset }));
+    }
+}
@@ -0,0 +1,543 @@
+use std::{collections::BTreeMap, fmt, ops::Range};
+use crate::interpreter::{Interpreter, Token};
+
+/// The parser behind `Interpreter::new`, kept alive between chunks of source so tools
+/// such as live editors can append to a program without parsing it again from the
+/// start. Additions and shifts since the last emitted token stay pending, so a chunk
+/// may end anywhere, even inside a loop.
+#[derive(Debug, Clone, Default)]
+pub struct Parser {
+    inst: Vec<Token>,
+    depth: i32,
+    shift: i32,
+    begin: usize,
+    // Ordered so pending additions, and the `AddTo`/`Mul` tokens of a collapsed loop
+    // such as `[>+>+<<-]`, come out by ascending offset on every parse.
+    mp: BTreeMap<i32, i16>,
+    // Where the additions in `mp` were written, by character position in the source.
+    spans: BTreeMap<i32, Range<usize>>,
+    pos: usize,
+    // The character position of every `[` and `]` left in `inst` as a loop token.
+    brackets: Vec<usize>,
+    report: OptReport,
+    // `inst[..settled]` through the token-level passes for a blank tape, where `settled`
+    // is a `LoopBegin` that later tokens follow, so `push` can no longer take it back.
+    // Only the first `head` tokens differ for any tape, and read `seeded_head` there.
+    settled: usize,
+    optimized: Vec<Token>,
+    head: usize,
+    seeded_head: Vec<Token>,
+}
+
+/// What the optimizer did to a program, from `Interpreter::new_with_report`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct OptReport {
+    /// `+` and `-` merged into an earlier one on the same cell.
+    pub adds_fused: usize,
+    /// Loops like `[-]`, now a `Clear` or `Set`.
+    pub clear_loops: usize,
+    /// Loops like `[->+<]` adding the cell to others once each, now `AddTo` tokens.
+    pub copy_loops: usize,
+    /// Loops like `[->++<]` adding multiples of the cell, now `Mul` tokens.
+    pub multiply_loops: usize,
+    /// Loops like `[>]` moving until a zero cell, now a `Scan`.
+    pub scan_loops: usize,
+    /// Collapsed loops whose `Clear` was dropped because the cell was already zero.
+    /// Their `AddTo` and `Mul` tokens are kept.
+    pub dead_loops: usize,
+    /// Cells a straight-line block adds more than 255 to, which is usually a mistake.
+    pub wraps: Vec<Wrap>,
+}
+
+/// A cell receiving a net addition of more than 255 between two loops or I/O
+/// commands, so that it wraps.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Wrap {
+    /// The cell, relative to the pointer at the start of the block.
+    pub offset: i32,
+    pub delta: i16,
+    /// Character positions of the first `+` or `-` on the cell and past the last one.
+    pub span: Range<usize>,
+}
+
+impl fmt::Display for Wrap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "cell at offset {} receives {:+}, wraps to {} (source {}..{}).",
+            self.offset, self.delta, self.delta as u8, self.span.start, self.span.end,
+        )
+    }
+}
+
+impl fmt::Display for OptReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "fused {} adds, converted {} clear loops, {} copy loops, {} multiply loops, {} scan loops, dropped {} dead loops.",
+            self.adds_fused, self.clear_loops, self.copy_loops, self.multiply_loops, self.scan_loops, self.dead_loops,
+        )?;
+        for wrap in &self.wraps {
+            write!(f, "\n{}", wrap)?;
+        }
+        Ok(())
+    }
+}
+
+/// Whether adding `add` changes a cell, which wraps modulo 256, so that e.g. 256 `+`
+/// emit nothing.
+fn changes(add: i16) -> bool {
+    add as u8 != 0
+}
+
+/// Takes the span of the pending addition at `offset`, noting a `Wrap` if it is one.
+fn lint(wraps: &mut Vec<Wrap>, spans: &mut BTreeMap<i32, Range<usize>>, offset: i32, delta: i16) {
+    let span = spans.remove(&offset).unwrap_or_default();
+    if delta.unsigned_abs() > 255 {
+        wraps.push(Wrap { offset, delta, span });
+    }
+}
+
+impl Parser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A parser with room for `hint` tokens; see `Interpreter::with_capacity`.
+    pub fn with_capacity(hint: usize) -> Self {
+        Self {
+            inst: Vec::with_capacity(hint),
+            ..Self::default()
+        }
+    }
+
+    /// How many loops are open at the end of the source fed so far.
+    pub fn depth(&self) -> i32 {
+        self.depth
+    }
+
+    /// The parse-time counts of the report, and the wraps so far including those still
+    /// pending; the token-level passes of `from_tokens` are not covered.
+    pub fn report(&self) -> OptReport {
+        let mut report = self.report.clone();
+        let mut spans = self.spans.clone();
+        for (shift, add) in &self.mp {
+            lint(&mut report.wraps, &mut spans, *shift, *add);
+        }
+        report
+    }
+
+    /// The pointer movement not yet emitted as a `Shift`.
+    pub fn pending_shift(&self) -> i32 {
+        self.shift
+    }
+
+    /// Parses another chunk of source. On error the chunk is consumed up to the
+    /// offending `]`, and what came before it is kept.
+    pub fn feed<I: IntoIterator<Item=char>>(&mut self, stream: I) -> Result<(), &'static str> {
+        let fed = stream.into_iter().try_for_each(|c| self.push(c));
+        self.settle();
+        fed
+    }
+
+    /// Runs the token-level passes up to the last `LoopBegin` that later tokens follow,
+    /// over the tokens since the last one.
+    fn settle(&mut self) {
+        let end = self.inst.len().saturating_sub(1);
+        let boundary = match self.inst[self.settled..end].iter().rposition(|token| matches!(token, Token::LoopBegin(_))) {
+            Some(at) if at > 0 => self.settled + at,
+            _ => return,
+        };
+        let tokens = &self.inst[self.settled..boundary];
+        if self.settled == 0 {
+            self.optimized = Interpreter::optimize_head(tokens, true);
+            self.head = self.optimized.len();
+            self.seeded_head = Interpreter::optimize_head(tokens, false);
+        } else {
+            self.optimized.extend(Interpreter::optimize_head(tokens, false));
+        }
+        self.settled = boundary;
+    }
+
+    fn push(&mut self, c: char) -> Result<(), &'static str> {
+        let Self { inst, depth, shift, begin, mp, spans, pos, brackets, report, .. } = self;
+        let at = *pos;
+        *pos += 1;
+        match match c {
+            '+' => Token::Add(1, 0),
+            '-' => Token::Add(-1, 0),
+            '>' => Token::Shift(1),
+            '<' => Token::Shift(-1),
+            ',' => Token::Input(0),
+            '.' => Token::Output(0),
+            '[' => {
+                *depth += 1;
+                Token::LoopBegin(0)
+            }
+            ']' => {
+                if *depth == 0 {
+                    return Err("[ missing.");
+                }
+                *depth -= 1;
+                Token::LoopEnd(0)
+            }
+            _ => return Ok(()),
+        } {
+            Token::Add(n, _) => {
+                spans.entry(*shift).or_insert(at..at).end = at + 1;
+                match mp.get_mut(shift) {
+                    None => { mp.insert(*shift, n); }
+                    Some(add) => {
+                        *add = add.wrapping_add(n);
+                        report.adds_fused += 1;
+                    }
+                }
+            }
+            Token::Shift(n) => {
+                *shift += n;
+            }
+            Token::Output(_) => {
+                if let Some(add) = mp.remove(shift) {
+                    lint(&mut report.wraps, spans, *shift, add);
+                    if changes(add) {
+                        inst.push(Token::Add(add, *shift));
+                    }
+                }
+                inst.push(Token::Output(*shift));
+            }
+            Token::Input(_) => {
+                mp.remove(shift);
+                spans.remove(shift);
+                inst.push(Token::Input(*shift));
+            }
+            Token::LoopBegin(_) => {
+                for (shift, add) in mp.iter() {
+                    lint(&mut report.wraps, spans, *shift, *add);
+                    if changes(*add) {
+                        inst.push(Token::Add(*add, *shift));
+                    }
+                }
+                mp.clear();
+                if *shift != 0 {
+                    inst.push(Token::Shift(*shift));
+                    *shift = 0;
+                }
+                inst.push(Token::LoopBegin(0));
+                brackets.push(at);
+                *begin = inst.len();
+            }
+            Token::LoopEnd(_) => {
+                // `.` and `,` always emit a token, so a body doing I/O never has
+                // `inst.len() == *begin` and is never collapsed.
+                if inst.len() == *begin && *shift == 0 && mp.get(&0).is_some_and(|&add| add as u8 == 0xff) {
+                    inst.pop().unwrap();
+                    brackets.pop();
+                    // Nothing was emitted inside the body, so a `Shift` right before the
+                    // popped `LoopBegin` can only be the one flushed at this `[`. Folding it
+                    // back into the pending shift keeps the loop's offsets independent of
+                    // whatever shift was outstanding when the loop was entered.
+                    if let Some(Token::Shift(prev_shift)) = inst.last() {
+                        *shift = *prev_shift;
+                        inst.pop();
+                    }
+                    mp.remove(&0);
+                    let adds = mp.values().filter(|&&add| changes(add));
+                    if adds.clone().next().is_none() {
+                        report.clear_loops += 1;
+                    } else if adds.clone().all(|&add| add as u8 == 1) {
+                        report.copy_loops += 1;
+                    } else {
+                        report.multiply_loops += 1;
+                    }
+                    for (offset, add) in mp.iter() {
+                        inst.push(match *add as u8 {
+                            0 => continue,
+                            1 => Token::AddTo(*offset + *shift, *shift),
+                            _ => Token::Mul(*add, *offset + *shift, *shift),
+                        });
+                    }
+                    inst.push(Token::Clear(*shift));
+                    mp.clear();
+                    spans.clear();
+                    *begin = 0;
+                } else {
+                    for (shift, add) in mp.iter() {
+                        lint(&mut report.wraps, spans, *shift, *add);
+                        if changes(*add) {
+                            inst.push(Token::Add(*add, *shift));
+                        }
+                    }
+                    if *shift != 0 {
+                        inst.push(Token::Shift(*shift));
+                        *shift = 0;
+                    }
+                    inst.push(Token::LoopEnd(0));
+                    brackets.push(at);
+                    mp.clear();
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Builds the program parsed so far, leaving the parser free to take more source.
+    /// The token-level passes of `from_tokens` only run again from the last loop each
+    /// `feed` left behind it; the tokens before it were optimized by that `feed`.
+    pub fn finish(&self) -> Result<Interpreter, &'static str> {
+        if self.depth > 0 {
+            return Err("] missing.");
+        }
+        let mut tail = Vec::with_capacity(self.inst.len() - self.settled + self.mp.len() + 2);
+        tail.extend_from_slice(&self.inst[self.settled..]);
+        for (shift, add) in &self.mp {
+            if changes(*add) {
+                tail.push(Token::Add(*add, *shift));
+            }
+        }
+        if self.shift != 0 {
+            tail.push(Token::Shift(self.shift));
+        }
+        tail.push(Token::End);
+        if self.settled == 0 {
+            return Interpreter::from_tokens(tail);
+        }
+        let tail = Interpreter::optimize(tail, false);
+        // Sized for the tail, so a large program is copied only once.
+        let mut inst = Vec::with_capacity(self.optimized.len() + tail.len());
+        inst.extend_from_slice(&self.optimized);
+        inst.extend_from_slice(&tail);
+        let mut seeded = Vec::with_capacity(inst.len() - self.head + self.seeded_head.len());
+        seeded.extend_from_slice(&self.seeded_head);
+        seeded.extend_from_slice(&inst[self.head..]);
+        Interpreter::from_optimized(inst, seeded)
+    }
+
+    /// `Interpreter::listing` for the program parsed so far, with the characters each
+    /// token came from. Loops keep their brackets through every pass, so those are
+    /// exact; the token-level passes reorder and merge the tokens between two brackets,
+    /// which are each given that whole stretch of source.
+    pub fn listing(&self) -> Result<String, &'static str> {
+        Ok(self.finish()?.listing_with(Some((&self.brackets, self.pos))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wraps(src: &str) -> Vec<Wrap> {
+        let mut parser = Parser::new();
+        parser.feed(src.chars()).unwrap();
+        parser.report().wraps
+    }
+
+    #[test]
+    fn wrap_is_reported_with_its_span() {
+        let src = format!(">>{}.", "+".repeat(300));
+        let wraps = wraps(&src);
+        assert_eq!(wraps, [Wrap { offset: 2, delta: 300, span: 2..302 }]);
+        assert_eq!(wraps[0].to_string(), "cell at offset 2 receives +300, wraps to 44 (source 2..302).");
+    }
+
+    #[test]
+    fn negative_wrap_shows_final_cell() {
+        let wraps = wraps(&"-".repeat(300));
+        assert_eq!(wraps[0].to_string(), "cell at offset 0 receives -300, wraps to 212 (source 0..300).");
+    }
+
+    #[test]
+    fn adds_within_a_byte_are_not_reported() {
+        assert!(wraps(&format!("{}>{}[-]", "+".repeat(255), "-".repeat(255))).is_empty());
+    }
+
+    #[test]
+    fn adds_overwritten_by_input_are_not_reported() {
+        assert!(wraps(&format!("{},", "+".repeat(300))).is_empty());
+    }
+
+    fn tokens(src: &str) -> Vec<Token> {
+        let mut parser = Parser::new();
+        parser.feed(src.chars()).unwrap();
+        parser.inst.clone()
+    }
+
+    #[test]
+    fn loops_doing_io_are_not_collapsed() {
+        for src in ["[.-]", "[-.]", "[,-]", "[-,]", "[>+.<-]"] {
+            let inst = tokens(src);
+            assert_eq!(inst.first(), Some(&Token::LoopBegin(0)), "{}", src);
+            assert_eq!(inst.last(), Some(&Token::LoopEnd(0)), "{}", src);
+            assert!(!inst.iter().any(|token| matches!(token, Token::Clear(_) | Token::AddTo(..) | Token::Mul(..))), "{}", src);
+        }
+    }
+
+    #[test]
+    fn loops_doing_io_print_every_iteration() {
+        let program = Interpreter::new("+++[.-]".chars()).unwrap();
+        assert_eq!(program.run_bytes(b"").unwrap(), [3, 2, 1]);
+        let program = Interpreter::new("+++[>+.<-]".chars()).unwrap();
+        assert_eq!(program.run_bytes(b"").unwrap(), [1, 2, 3]);
+        let program = Interpreter::new(",[.-,]".chars()).unwrap();
+        assert_eq!(program.run_bytes(&[3, 7, 1]).unwrap(), [3, 7, 1]);
+    }
+
+    fn has_zero_add(inst: &[Token]) -> bool {
+        inst.iter().any(|token| matches!(*token, Token::Add(n, _) | Token::Mul(n, ..) if n as u8 == 0))
+    }
+
+    #[test]
+    fn cancelled_adds_are_never_emitted() {
+        let plus = "+".repeat(256);
+        for src in ["+-[.-]", "+-.", "+-,", ">+-<[.-]", "+-", &plus, &format!("{}.", plus), &format!("[->{}<]", plus)] {
+            let inst = tokens(src);
+            assert!(!has_zero_add(&inst), "{}: {:?}", src, inst);
+            let program = Interpreter::new(src.chars()).unwrap();
+            assert!(!has_zero_add(program.tokens()), "{}: {:?}", src, program.tokens());
+        }
+    }
+
+    #[test]
+    fn cancelled_adds_leave_the_cell_alone() {
+        assert_eq!(tokens("+-[.-]")[0], Token::LoopBegin(0));
+        assert_eq!(tokens("+-."), [Token::Output(0)]);
+        assert_eq!(tokens("+-,"), [Token::Input(0)]);
+        let program = Interpreter::new(format!(",{}.", "+".repeat(256)).chars()).unwrap();
+        assert_eq!(program.run_bytes(&[42]).unwrap(), [42]);
+    }
+
+    #[test]
+    fn decrement_by_257_still_clears() {
+        let inst = tokens(&format!("[{}]", "-".repeat(257)));
+        assert_eq!(inst, [Token::Clear(0)]);
+    }
+
+    fn moves(n: i32) -> String {
+        (if n < 0 { "<" } else { ">" }).repeat(n.unsigned_abs() as usize)
+    }
+
+    #[test]
+    fn copy_loops_collapse_whatever_shift_is_pending() {
+        let mut seed = 0x9e37_79b9_7f4a_7c15u64;
+        let mut next = move |n: u64| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed % n
+        };
+        for _ in 0..500 {
+            // The pointer starts at cell 8 and stays within 4..=12 outside the loop, so
+            // the loop body, reaching 3 cells either way, never leaves the 20 printed.
+            let mut src = moves(8);
+            let mut pos = 8;
+            for _ in 0..next(4) {
+                let to = 4 + next(9) as i32;
+                src += &moves(to - pos);
+                src += &"+".repeat(next(4) as usize);
+                pos = to;
+            }
+            let start = pos;
+            let mut body = String::from("-");
+            let mut at = 0;
+            for _ in 0..1 + next(3) {
+                let to = [-3, -2, -1, 1, 2, 3][next(6) as usize];
+                body += &moves(to - at);
+                body += &"+".repeat(1 + next(3) as usize);
+                at = to;
+            }
+            body += &moves(-at);
+            if next(2) == 0 {
+                body = body.chars().rev().collect::<String>().replace('<', "_").replace('>', "<").replace('_', ">");
+            }
+            let loop_src = format!(",[{}]", body);
+            let after = 4 + next(9) as i32;
+            let src = format!("{}{}{}{}", src, loop_src, moves(after - start), moves(-after)) + &".>".repeat(20);
+            let inst = tokens(&src);
+            assert!(!inst.iter().any(|token| matches!(token, Token::LoopBegin(_))), "{}: {:?}", src, inst);
+            assert!(inst.iter().any(|token| matches!(token, Token::AddTo(..) | Token::Mul(..))), "{}: {:?}", src, inst);
+            crate::testing::assert_backends_agree(&src, &[next(256) as u8], 100_000);
+        }
+    }
+
+    #[test]
+    fn copy_loop_offsets_follow_a_pending_shift() {
+        assert_eq!(tokens(">>[->+<]"), [Token::AddTo(3, 2), Token::Clear(2)]);
+        assert_eq!(tokens(">>[-<<+++>>]"), [Token::Mul(3, 0, 2), Token::Clear(2)]);
+        let program = Interpreter::new(",>>+<<[->>>+<<<]>>>.<.".chars()).unwrap();
+        assert_eq!(program.run_bytes(&[7]).unwrap(), [7, 1]);
+    }
+
+    /// `finish` with every pass run over the whole token stream.
+    fn unsettled(parser: &Parser) -> Interpreter {
+        Parser { settled: 0, ..parser.clone() }.finish().unwrap()
+    }
+
+    #[test]
+    fn fed_chunks_are_only_optimized_from_the_last_loop() {
+        let mut parser = Parser::new();
+        parser.feed("+>+<[->+<]".chars()).unwrap();
+        assert_eq!(parser.settled, 0);
+        parser.feed("[>]+[-].".chars()).unwrap();
+        let settled = parser.settled;
+        assert!(settled > 0);
+        parser.feed("++.".chars()).unwrap();
+        assert_eq!(parser.settled, settled);
+        assert_eq!(parser.finish().unwrap().tokens(), unsettled(&parser).tokens());
+    }
+
+    #[test]
+    fn optimizing_from_the_last_loop_matches_optimizing_everything() {
+        let mut seed = 0x9e37_79b9_7f4a_7c15u64;
+        let mut next = move |n: u64| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed % n
+        };
+        for _ in 0..2000 {
+            let mut src = String::new();
+            let mut depth = 0;
+            for _ in 0..next(40) {
+                match next(12) {
+                    0 => {
+                        src.push('[');
+                        depth += 1;
+                    }
+                    1 | 2 if depth > 0 => {
+                        src.push(']');
+                        depth -= 1;
+                    }
+                    n => src.push(b"+-><.,-+><+-"[n as usize] as char),
+                }
+            }
+            src += &"]".repeat(depth);
+            let mut parser = Parser::new();
+            let mut rest = &src[..];
+            while !rest.is_empty() {
+                let (chunk, after) = rest.split_at(1 + next(rest.len() as u64) as usize);
+                parser.feed(chunk.chars()).unwrap();
+                rest = after;
+            }
+            let (program, whole) = (parser.finish().unwrap(), unsettled(&parser));
+            assert_eq!(program.tokens(), whole.tokens(), "{}", src);
+            assert_eq!(program.any_tape_tokens(), whole.any_tape_tokens(), "{}", src);
+        }
+    }
+
+    #[test]
+    fn duplicate_into_two_is_two_add_tos_and_a_clear() {
+        let expected = [Token::AddTo(1, 0), Token::AddTo(2, 0), Token::Clear(0)];
+        assert_eq!(tokens("[>+>+<<-]"), expected);
+        assert_eq!(tokens("[->+>+<<]"), expected);
+        assert_eq!(tokens("[>>+<+<-]"), expected);
+    }
+
+    #[test]
+    fn duplicate_into_two_matches_the_naive_interpreter() {
+        for value in 0..=255u8 {
+            crate::testing::assert_backends_agree(",[>+>+<<-]>>[-<+>]<.>.<<.", &[value], 10_000);
+            let output = crate::testing::run_bytes_limited(",>>+<<[>+>+<<-]>.>.<<.", &[value]).unwrap();
+            assert_eq!(output, [value, value.wrapping_add(1), 0]);
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,58 @@
+use std::io::{self, Read, Write};
+use crate::{
+    error::ExecError,
+    interpreter::Interpreter,
+};
+
+/// Programs run as filters one after another, each reading what the one before it
+/// wrote. Stages run to completion in turn through `execute`, with the output in
+/// between buffered in memory, so a stage cannot react to later output of the next.
+#[derive(Debug, Default, Clone)]
+pub struct Pipeline<'a> {
+    stages: Vec<&'a Interpreter>,
+}
+
+impl<'a> Pipeline<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `stage` at the end.
+    pub fn then(mut self, stage: &'a Interpreter) -> Self {
+        self.stages.push(stage);
+        self
+    }
+
+    /// Feeds `reader` to the first stage and writes the output of the last one to
+    /// `writer`; an empty pipeline copies its input through. Stops at the first stage
+    /// that fails.
+    pub fn run(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<(), ExecError> {
+        let (last, init) = match self.stages.split_last() {
+            Some(split) => split,
+            None => {
+                io::copy(reader, writer).unwrap();
+                return Ok(());
+            }
+        };
+        let mut piped: Option<Vec<u8>> = None;
+        for stage in init {
+            let mut output = Vec::new();
+            match piped {
+                Some(ref input) => stage.execute(&mut &input[..], &mut output)?,
+                None => stage.execute(reader, &mut output)?,
+            };
+            piped = Some(output);
+        }
+        match piped {
+            Some(ref input) => last.execute(&mut &input[..], writer)?,
+            None => last.execute(reader, writer)?,
+        };
+        Ok(())
+    }
+
+    pub fn run_bytes(&self, input: &[u8]) -> Result<Vec<u8>, ExecError> {
+        let mut output = Vec::new();
+        self.run(&mut &input[..], &mut output)?;
+        Ok(output)
+    }
+}
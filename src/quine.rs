@@ -0,0 +1,20 @@
+use crate::{
+    interpreter::Interpreter,
+    session::Step,
+};
+
+impl Interpreter {
+    /// Whether the program prints exactly `source`, taken to be the program's own
+    /// source, without reading any input. A program that tries to read is not a quine,
+    /// and one failing or hitting the configured step limit or timeout is not either.
+    pub fn is_quine(&self, source: &str) -> bool {
+        let mut session = self.session();
+        matches!(session.resume(), Ok(Step::Finished)) && session.take_output() == source.as_bytes()
+    }
+
+    /// Whether the program echoes `input` unchanged, i.e. `input` is a fixed point of
+    /// the program seen as a function from input to output.
+    pub fn is_fixed_point(&self, input: &[u8]) -> bool {
+        matches!(self.run_bytes(input), Ok(output) if output == input)
+    }
+}
@@ -0,0 +1,39 @@
+use std::collections::HashMap;
+use crate::interpreter::{CompiledProgram, Interpreter};
+
+/// A library of named programs, each JIT-compiled once when registered so that
+/// repeated runs skip parsing and assembly.
+#[derive(Default)]
+pub struct Registry {
+    programs: HashMap<String, CompiledProgram>,
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parses and compiles `src` under `name`, replacing any program already there.
+    pub fn register(&mut self, name: &str, src: &str) -> Result<(), &'static str> {
+        let compiled = Interpreter::new(src.chars())?.compile();
+        self.programs.insert(name.to_string(), compiled);
+        Ok(())
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.programs.contains_key(name)
+    }
+
+    pub fn remove(&mut self, name: &str) -> bool {
+        self.programs.remove(name).is_some()
+    }
+
+    /// Runs the program registered as `name` on `input` and returns its output, or `None`
+    /// if nothing is registered under that name.
+    pub fn run(&self, name: &str, input: &[u8]) -> Option<Vec<u8>> {
+        let program = self.programs.get(name)?;
+        let mut output = Vec::new();
+        program(&mut &input[..], &mut output);
+        Some(output)
+    }
+}
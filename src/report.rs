@@ -0,0 +1,76 @@
+use std::time::Duration;
+use crate::{
+    config::ExecConfig,
+    error::ExecError,
+    interpreter::{Interpreter, Meter},
+    io::{SliceInput, VecOutput},
+};
+
+/// Why a reported run stopped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum ExitReason {
+    Normal,
+    StepLimit,
+    Timeout,
+    Error(String),
+}
+
+/// Everything a frontend needs to render one run. Serializes `output` as base64.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct RunReport {
+    #[cfg_attr(feature = "serde", serde(serialize_with = "base64"))]
+    pub output: Vec<u8>,
+    pub exit: ExitReason,
+    pub steps: u64,
+    pub pointer: i32,
+}
+
+impl Interpreter {
+    /// Interprets the program on `input`, stopping after `max_steps` tokens or once
+    /// `timeout` has passed, and describes the outcome instead of failing.
+    pub fn report(&self, input: &[u8], max_steps: Option<u64>, timeout: Option<Duration>) -> RunReport {
+        let config = ExecConfig {
+            max_steps,
+            timeout,
+            ..self.config().clone()
+        };
+        let mut meter = Meter {
+            count_steps: true,
+            ..Meter::default()
+        };
+        let mut tape = config.tape.clone().unwrap_or_default();
+        let mut output = VecOutput::new();
+        let result = self.for_tape(&config).interpret(&config, &mut tape, &mut SliceInput::new(input), &mut output, &mut meter);
+        let exit = match result {
+            Ok(()) => ExitReason::Normal,
+            Err(ExecError::StepLimit { .. }) => ExitReason::StepLimit,
+            Err(ExecError::TimedOut) => ExitReason::Timeout,
+            Err(e) => ExitReason::Error(e.to_string()),
+        };
+        RunReport {
+            output: output.into_inner(),
+            exit,
+            steps: meter.steps,
+            pointer: meter.pointer,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+fn base64<S: serde::Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            encoded.push(if i <= chunk.len() {
+                ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char
+            } else {
+                '='
+            });
+        }
+    }
+    serializer.serialize_str(&encoded)
+}
@@ -0,0 +1,147 @@
+use std::{
+    io::{Read, Write},
+    ops::Range,
+};
+use crate::{
+    config::ExecConfig,
+    error::ExecError,
+    interpreter::{call_buffer, Assembled, Interpreter, Token},
+    tape::Tape,
+};
+
+struct Piece {
+    span: Range<usize>,
+    // The body of a top-level loop, run once per iteration, rather than straight-line
+    // code between loops, run once.
+    looped: bool,
+    code: Assembled,
+}
+
+/// A program JIT-compiled one top-level loop body at a time, for stepping through it at
+/// native speed between stops. The straight-line code between top-level loops is
+/// compiled the same way, and the loop conditions are checked in Rust.
+pub struct Segments {
+    pieces: Vec<Piece>,
+    tape: Tape,
+    checked_len: Option<usize>,
+    reset_pointer: bool,
+}
+
+/// A run of `Segments` on its own tape, advanced one piece at a time.
+pub struct SegmentRun<'a> {
+    segments: &'a Segments,
+    next: usize,
+    tape: Tape,
+}
+
+impl Segments {
+    pub fn start(&self) -> SegmentRun<'_> {
+        SegmentRun {
+            segments: self,
+            next: 0,
+            tape: self.tape.clone(),
+        }
+    }
+
+    /// How many pieces the program was split into.
+    pub fn len(&self) -> usize {
+        self.pieces.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pieces.is_empty()
+    }
+}
+
+impl SegmentRun<'_> {
+    /// Runs one piece: the straight-line code up to the next top-level loop, or one
+    /// iteration of that loop. Returns the span of program tokens it covers, or `None`
+    /// once the program has ended. Under `EofPolicy::InfiniteZeros` the reader is only
+    /// fused for the piece it is passed to.
+    pub fn step(&mut self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<Option<Range<usize>>, ExecError> {
+        while let Some(piece) = self.segments.pieces.get(self.next) {
+            if piece.looped {
+                let pointer = self.tape.pointer();
+                match self.tape.cells().get(pointer) {
+                    Some(0) => {
+                        self.next += 1;
+                        continue;
+                    }
+                    Some(_) => {}
+                    None => return Err(ExecError::OutOfBounds { pos: pointer as i32 }),
+                }
+            } else {
+                self.next += 1;
+            }
+            if let Some(len) = self.segments.checked_len {
+                assert_eq!(self.tape.cells().len(), len, "tape length differs from the compiled one.");
+            }
+            let Self { tape, .. } = self;
+            // SAFETY: `entry` is the win64 entry `assemble` put in `buf`, and checked code
+            // was assembled for the length of `tape`, asserted above.
+            let pointer = piece.code.eof.with_reader(reader, |reader| unsafe {
+                call_buffer(&piece.code.buf, piece.code.entry, tape, reader, writer)
+            })?;
+            self.tape.set_pointer(pointer);
+            return Ok(Some(piece.span.clone()));
+        }
+        if self.segments.reset_pointer {
+            self.tape.set_pointer(0);
+        }
+        Ok(None)
+    }
+
+    /// Runs the remaining pieces.
+    pub fn finish(&mut self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<(), ExecError> {
+        while self.step(reader, writer)?.is_some() {}
+        Ok(())
+    }
+
+    pub fn tape(&self) -> &Tape {
+        &self.tape
+    }
+}
+
+impl Interpreter {
+    /// Compiles the program for stepping through it loop by loop; see `Segments`.
+    pub fn compile_segments(&self) -> Segments {
+        // Each piece hands the pointer to the next, so only the last step resets it.
+        let config = &ExecConfig {
+            reset_pointer: false,
+            ..self.config().clone()
+        };
+        let compile = |tokens: &[Token]| {
+            let mut inst = tokens.to_vec();
+            inst.push(Token::End);
+            Self::with_tokens(inst, config.clone()).assemble(config)
+        };
+        let program = self.for_tape(config);
+        let inst = &program.tokens()[..program.tokens().len() - 1];
+        let mut pieces = Vec::new();
+        let mut straight = 0;
+        let mut i = 0;
+        while i < inst.len() {
+            if let Token::LoopBegin(label) = inst[i] {
+                if straight < i {
+                    pieces.push(Piece { span: straight..i, looped: false, code: compile(&inst[straight..i]) });
+                }
+                let end = (i as i32 + label) as usize;
+                pieces.push(Piece { span: i..end, looped: true, code: compile(&inst[i + 1..end - 1]) });
+                i = end;
+                straight = end;
+            } else {
+                i += 1;
+            }
+        }
+        if straight < inst.len() {
+            pieces.push(Piece { span: straight..inst.len(), looped: false, code: compile(&inst[straight..]) });
+        }
+        let tape = config.tape.clone().unwrap_or_default();
+        Segments {
+            pieces,
+            checked_len: if config.bounds_check { Some(tape.cells().len()) } else { None },
+            tape,
+            reset_pointer: self.config().reset_pointer,
+        }
+    }
+}
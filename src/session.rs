@@ -0,0 +1,409 @@
+use std::{
+    borrow::Cow,
+    collections::VecDeque,
+    convert::TryFrom,
+    io::{self, Read},
+    iter::Copied,
+    mem,
+    slice,
+};
+use crate::{
+    bytecode::{decode_tokens, encode_tokens, read_varint, write_varint},
+    config::ExecConfig,
+    error::ExecError,
+    interpreter::{Interpreter, Meter, Token},
+    tape::Tape,
+};
+
+/// Where a `Session` stopped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Step {
+    /// The program wants a byte that has not been fed yet.
+    NeedsInput,
+    /// Only from `run_until_output`: the bytes the program is about to write, which it
+    /// writes once resumed.
+    Output(Vec<u8>),
+    /// Only from `resume_for`: the step budget ran out before the program stopped.
+    Yielded,
+    Finished,
+}
+
+/// A run of a program that pauses whenever it reads past the input fed so far, so the
+/// host can look at the output and decide what to feed next. Reads after
+/// `close_input` get the configured EOF value instead of pausing.
+#[derive(Debug)]
+pub struct Session<'a> {
+    // As optimized for the configured tape, if there is one.
+    program: Cow<'a, Interpreter>,
+    tape: Tape,
+    next: usize,
+    input: VecDeque<u8>,
+    closed: bool,
+    finished: bool,
+    // Stopped before an output, which the next run must not stop on again.
+    held: bool,
+    output: Vec<u8>,
+}
+
+struct Pending<'a> {
+    input: &'a mut VecDeque<u8>,
+    closed: bool,
+}
+
+impl Read for Pending<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.input.is_empty() && !self.closed {
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+        self.input.read(buf)
+    }
+}
+
+impl<'a> Session<'a> {
+    pub fn new(program: &'a Interpreter) -> Self {
+        Self {
+            program: program.for_tape(program.config()),
+            tape: program.config().tape.clone().unwrap_or_default(),
+            next: 0,
+            input: VecDeque::new(),
+            closed: false,
+            finished: false,
+            held: false,
+            output: Vec::new(),
+        }
+    }
+
+    pub fn feed(&mut self, input: &[u8]) {
+        self.input.extend(input);
+    }
+
+    pub fn close_input(&mut self) {
+        self.closed = true;
+    }
+
+    /// Interprets until the program needs more input or ends. Step limits and timeouts
+    /// apply to each call on its own.
+    pub fn resume(&mut self) -> Result<Step, ExecError> {
+        self.advance(false, None)
+    }
+
+    /// Like `resume`, but stops with `Step::Yielded` after `steps` tokens, so a host
+    /// can take the output of a program that never reads and never ends.
+    pub fn resume_for(&mut self, steps: u64) -> Result<Step, ExecError> {
+        self.advance(false, Some(steps))
+    }
+
+    /// Like `resume`, but also stops right before each `.`, with the tape as the output
+    /// sees it. Runs through the interpreter's inspecting loop, which is slower.
+    pub fn run_until_output(&mut self) -> Result<Step, ExecError> {
+        self.advance(true, None)
+    }
+
+    fn advance(&mut self, pause_output: bool, budget: Option<u64>) -> Result<Step, ExecError> {
+        if self.finished {
+            return Ok(Step::Finished);
+        }
+        let mut meter = Meter {
+            start: self.next,
+            pause_output,
+            resume_output: mem::take(&mut self.held),
+            budget,
+            ..Meter::default()
+        };
+        let mut reader = Pending {
+            input: &mut self.input,
+            closed: self.closed,
+        };
+        match self.program.interpret(self.program.config(), &mut self.tape, &mut reader, &mut self.output, &mut meter) {
+            Ok(()) => match meter.yielded {
+                Some(index) => {
+                    self.next = index;
+                    Ok(Step::Yielded)
+                }
+                None => {
+                    self.finished = true;
+                    Ok(Step::Finished)
+                }
+            },
+            Err(ExecError::NeedsInput { index }) => {
+                self.next = index;
+                Ok(Step::NeedsInput)
+            }
+            Err(ExecError::Paused { index }) => {
+                self.next = index;
+                self.held = true;
+                let bytes = match self.program.tokens()[index] {
+                    Token::Output(shift) => {
+                        let cell = self.tape.pointer() as i32 + shift;
+                        match self.tape.cells().get(cell as usize) {
+                            Some(&byte) => vec![byte],
+                            // Let the resumed run fail on it.
+                            None => return self.advance(pause_output, budget),
+                        }
+                    }
+                    Token::OutputBytes(ref literal) => literal.clone(),
+                    _ => unreachable!(),
+                };
+                Ok(Step::Output(bytes))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// The paused run as bytes that `Interpreter::from_checkpoint` and `restore` take
+    /// back, possibly in another process: the program's bytecode, the non-zero runs of
+    /// the tape, the pointer and next token, and any input or output not taken yet.
+    pub fn save(&self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        let program = encode_tokens(self.program.tokens());
+        write_varint(&mut out, program.len() as i32);
+        out.extend(program);
+        let cells = self.tape.cells();
+        write_varint(&mut out, cells.len() as i32);
+        write_varint(&mut out, self.tape.pointer() as i32);
+        let mut runs = Vec::new();
+        let mut i = 0;
+        while i < cells.len() {
+            if cells[i] == 0 {
+                i += 1;
+                continue;
+            }
+            let start = i;
+            while i < cells.len() && cells[i] != 0 {
+                i += 1;
+            }
+            runs.push(start..i);
+        }
+        write_varint(&mut out, runs.len() as i32);
+        for run in runs {
+            write_varint(&mut out, run.start as i32);
+            write_varint(&mut out, run.len() as i32);
+            out.extend_from_slice(&cells[run]);
+        }
+        write_varint(&mut out, self.next as i32);
+        out.push(self.finished as u8 | (self.held as u8) << 1 | (self.closed as u8) << 2);
+        write_varint(&mut out, self.input.len() as i32);
+        out.extend(&self.input);
+        write_varint(&mut out, self.output.len() as i32);
+        out.extend_from_slice(&self.output);
+        out
+    }
+
+    /// Resumes a run saved with `save`. `program` has to be the saved one, usually
+    /// from `Interpreter::from_checkpoint` on the same bytes, and its config is the one
+    /// the run goes on with. The saved tape has to be as long as the configured one, or
+    /// within its limit if that is growable.
+    pub fn restore(program: &'a Interpreter, bytes: &[u8]) -> Result<Self, &'static str> {
+        let mut bytes = Checkpoint::open(bytes)?;
+        let program = program.for_tape(program.config());
+        if bytes.block()? != encode_tokens(program.tokens()) {
+            return Err("checkpoint is for another program.");
+        }
+        let mut tape = program.config().tape.clone().unwrap_or_default()
+            .blank(bytes.len()?)
+            .ok_or("tape size differs from the configured tape.")?;
+        let pointer = bytes.len()?;
+        if pointer >= tape.cells().len() {
+            return Err("pointer out of range.");
+        }
+        for _ in 0..bytes.len()? {
+            let start = bytes.len()?;
+            let run = bytes.block()?;
+            tape.cells_mut()
+                .get_mut(start..start + run.len())
+                .ok_or("tape run out of range.")?
+                .copy_from_slice(&run);
+        }
+        tape.set_pointer(pointer);
+        let next = bytes.len()?;
+        if next >= program.tokens().len() {
+            return Err("token index out of range.");
+        }
+        let flags = bytes.take(1)?[0];
+        let input = bytes.block()?.into();
+        let output = bytes.block()?;
+        Ok(Self {
+            program,
+            tape,
+            next,
+            input,
+            closed: flags & 4 != 0,
+            finished: flags & 1 != 0,
+            held: flags & 2 != 0,
+            output,
+        })
+    }
+
+    /// The output written since the last call.
+    pub fn take_output(&mut self) -> Vec<u8> {
+        mem::take(&mut self.output)
+    }
+
+    pub fn tape(&self) -> &Tape {
+        &self.tape
+    }
+}
+
+const MAGIC: &[u8] = b"BFS1";
+
+struct Checkpoint<'a>(Copied<slice::Iter<'a, u8>>);
+
+impl<'a> Checkpoint<'a> {
+    fn open(bytes: &'a [u8]) -> Result<Self, &'static str> {
+        match bytes.strip_prefix(MAGIC) {
+            Some(rest) => Ok(Self(rest.iter().copied())),
+            None => Err("not a session checkpoint."),
+        }
+    }
+
+    fn len(&mut self) -> Result<usize, &'static str> {
+        usize::try_from(read_varint(&mut self.0)?).map_err(|_| "negative length in checkpoint.")
+    }
+
+    fn take(&mut self, len: usize) -> Result<Vec<u8>, &'static str> {
+        let bytes: Vec<u8> = self.0.by_ref().take(len).collect();
+        if bytes.len() != len {
+            return Err("truncated checkpoint.");
+        }
+        Ok(bytes)
+    }
+
+    fn block(&mut self) -> Result<Vec<u8>, &'static str> {
+        let len = self.len()?;
+        self.take(len)
+    }
+}
+
+impl Interpreter {
+    /// The program saved in a `Session::save` checkpoint, with its tokens exactly as
+    /// they were so the saved position still points at the same one. It gets the
+    /// default config; change it through `config_mut` before restoring.
+    pub fn from_checkpoint(bytes: &[u8]) -> Result<Self, &'static str> {
+        let program = Checkpoint::open(bytes)?.block()?;
+        Self::with_tokens(decode_tokens(&program)?, ExecConfig::default()).build_jump_addr()
+    }
+
+    /// Starts an interactive run; see `Session`.
+    pub fn session(&self) -> Session<'_> {
+        Session::new(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tape::TAPE_SIZE;
+
+    /// A checkpoint of `program` about to run its first token, on a blank tape of `len`
+    /// cells with the pointer on `pointer`.
+    fn checkpoint(program: &Interpreter, len: i32, pointer: i32) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        let tokens = encode_tokens(program.tokens());
+        write_varint(&mut out, tokens.len() as i32);
+        out.extend(tokens);
+        write_varint(&mut out, len);
+        write_varint(&mut out, pointer);
+        // No tape runs, token 0 next, no flags, no input and no output.
+        out.extend([0, 0, 0, 0, 0]);
+        out
+    }
+
+    #[test]
+    fn session_runs_on_configured_tape() {
+        let mut program = Interpreter::new("+.+.".chars()).unwrap();
+        program.config_mut().tape = Some(Tape::from_cells(&[65]));
+        let mut session = program.session();
+        assert_eq!(session.resume().unwrap(), Step::Finished);
+        assert_eq!(session.take_output(), [66, 67]);
+    }
+
+    #[test]
+    fn restored_session_matches_uninterrupted_run() {
+        let program = Interpreter::new(",[.>+++<,]>.".chars()).unwrap();
+        let full = program.run_bytes(b"hello").unwrap();
+        let mut session = program.session();
+        session.feed(b"he");
+        assert_eq!(session.resume().unwrap(), Step::NeedsInput);
+        let checkpoint = session.save();
+        let restored = Interpreter::from_checkpoint(&checkpoint).unwrap();
+        let mut session = Session::restore(&restored, &checkpoint).unwrap();
+        session.feed(b"llo");
+        session.close_input();
+        assert_eq!(session.resume().unwrap(), Step::Finished);
+        assert_eq!(session.take_output(), full);
+    }
+
+    #[test]
+    fn restored_session_keeps_seeded_tape() {
+        let mut program = Interpreter::new("+.,+.".chars()).unwrap();
+        program.config_mut().tape = Some(Tape::from_cells(&[65]));
+        let mut session = program.session();
+        assert_eq!(session.resume().unwrap(), Step::NeedsInput);
+        let checkpoint = session.save();
+        let mut restored = Interpreter::from_checkpoint(&checkpoint).unwrap();
+        restored.config_mut().tape = Some(Tape::from_cells(&[65]));
+        let mut session = Session::restore(&restored, &checkpoint).unwrap();
+        session.feed(b"A");
+        assert_eq!(session.resume().unwrap(), Step::Finished);
+        assert_eq!(session.take_output(), [66, 66]);
+    }
+
+    #[test]
+    fn checkpoint_of_another_program_is_refused() {
+        let program = Interpreter::new(",.".chars()).unwrap();
+        let mut session = program.session();
+        session.resume().unwrap();
+        let other = Interpreter::new("+".chars()).unwrap();
+        assert!(Session::restore(&other, &session.save()).is_err());
+    }
+
+    #[test]
+    fn resume_for_yields_and_goes_on() {
+        let program = Interpreter::new("+[.-]".chars()).unwrap();
+        let mut session = program.session();
+        let mut steps = 0;
+        loop {
+            match session.resume_for(2).unwrap() {
+                Step::Yielded => steps += 1,
+                Step::Finished => break,
+                step => panic!("{:?}", step),
+            }
+        }
+        assert!(steps > 1);
+        assert_eq!(session.take_output(), [1]);
+    }
+
+    #[test]
+    fn resume_for_bounds_an_endless_program() {
+        let program = Interpreter::new("+[.]".chars()).unwrap();
+        let mut session = program.session();
+        assert_eq!(session.resume_for(1000).unwrap(), Step::Yielded);
+        let first = session.take_output().len();
+        assert!(first > 0 && first < 1000);
+        assert_eq!(session.resume_for(1000).unwrap(), Step::Yielded);
+        assert!(session.take_output().len() < 1000);
+    }
+
+    #[test]
+    fn restore_refuses_a_tape_of_another_size() {
+        let mut program = Interpreter::new(",.".chars()).unwrap();
+        assert!(Session::restore(&program, &checkpoint(&program, TAPE_SIZE as i32, 0)).is_ok());
+        for len in [0, 16, TAPE_SIZE as i32 + 1, i32::MAX] {
+            let result = Session::restore(&program, &checkpoint(&program, len, 0));
+            assert_eq!(result.err(), Some("tape size differs from the configured tape."));
+        }
+        program.config_mut().tape = Some(Tape::growable(0x10000));
+        let session = Session::restore(&program, &checkpoint(&program, 0x8000, 0)).unwrap();
+        assert_eq!(session.tape().limit(), Some(0x10000));
+        assert!(Session::restore(&program, &checkpoint(&program, 0x10001, 0)).is_err());
+    }
+
+    #[test]
+    fn restore_refuses_a_pointer_off_the_tape() {
+        let program = Interpreter::new(",.".chars()).unwrap();
+        let session = Session::restore(&program, &checkpoint(&program, TAPE_SIZE as i32, TAPE_SIZE as i32 - 1)).unwrap();
+        assert_eq!(session.tape().pointer(), TAPE_SIZE - 1);
+        let result = Session::restore(&program, &checkpoint(&program, TAPE_SIZE as i32, TAPE_SIZE as i32));
+        assert_eq!(result.err(), Some("pointer out of range."));
+    }
+}
\ No newline at end of file
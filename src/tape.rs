@@ -0,0 +1,221 @@
+use std::{
+    io::{self, Write},
+    ops::{Bound, Deref, DerefMut, RangeBounds},
+};
+use crate::error::ExecError;
+
+pub const TAPE_SIZE: usize = 0xffff;
+
+/// How many cells a growable tape starts with, or its limit if that is smaller.
+pub const GROWABLE_START: usize = 0x1000;
+
+/// A run's cells together with where the pointer is, either as left by a finished run
+/// or as the starting state for `Interpreter::run_on`.
+#[derive(Debug, Clone)]
+pub struct Tape {
+    cells: Cells,
+    pointer: usize,
+    /// The most cells a growable tape may grow to; `None` for a fixed tape.
+    limit: Option<usize>,
+}
+
+#[derive(Debug)]
+enum Cells {
+    Heap(Vec<u8>),
+    #[cfg(all(feature = "mmap-tape", unix))]
+    Mapped(memmap2::MmapMut),
+}
+
+impl Clone for Cells {
+    fn clone(&self) -> Self {
+        match self {
+            Cells::Heap(cells) => Cells::Heap(cells.clone()),
+            // Only pages holding a non-zero cell are copied, so a sparse copy stays sparse.
+            #[cfg(all(feature = "mmap-tape", unix))]
+            Cells::Mapped(cells) => {
+                let mut copy = memmap2::MmapMut::map_anon(cells.len()).expect("failed to map tape.");
+                for (from, to) in cells.chunks(PAGE_SIZE).zip(copy.chunks_mut(PAGE_SIZE)) {
+                    if from.iter().any(|&c| c != 0) {
+                        to.copy_from_slice(from);
+                    }
+                }
+                Cells::Mapped(copy)
+            }
+        }
+    }
+}
+
+#[cfg(all(feature = "mmap-tape", unix))]
+const PAGE_SIZE: usize = 0x1000;
+
+impl Deref for Cells {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Cells::Heap(cells) => cells,
+            #[cfg(all(feature = "mmap-tape", unix))]
+            Cells::Mapped(cells) => cells,
+        }
+    }
+}
+
+impl DerefMut for Cells {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        match self {
+            Cells::Heap(cells) => cells,
+            #[cfg(all(feature = "mmap-tape", unix))]
+            Cells::Mapped(cells) => cells,
+        }
+    }
+}
+
+impl Tape {
+    pub fn new() -> Self {
+        Self {
+            cells: Cells::Heap(vec![0; TAPE_SIZE]),
+            pointer: 0,
+            limit: None,
+        }
+    }
+
+    /// A blank tape of `len` cells in an anonymous mapping, so pages are only committed
+    /// once a program touches them. Falls back to a zeroed `Vec` where mmap is unavailable.
+    /// The interpreter bounds checks against `len`; the JIT does not check at all.
+    #[cfg(feature = "mmap-tape")]
+    pub fn mapped(len: usize) -> io::Result<Self> {
+        #[cfg(unix)]
+        let cells = Cells::Mapped(memmap2::MmapMut::map_anon(len)?);
+        #[cfg(not(unix))]
+        let cells = Cells::Heap(vec![0; len]);
+        Ok(Self {
+            cells,
+            pointer: 0,
+            limit: None,
+        })
+    }
+
+    /// A blank tape of `len` cells.
+    pub(crate) fn zeroed(len: usize) -> Self {
+        Self {
+            cells: Cells::Heap(vec![0; len]),
+            pointer: 0,
+            limit: None,
+        }
+    }
+
+    /// A blank tape that the interpreter extends to the right whenever the program moves
+    /// past its end, up to `limit` cells in all; going further fails with
+    /// `ExecError::TapeLimitExceeded`. The JIT and the threaded backend do not grow it
+    /// and see only the cells it holds when they start.
+    pub fn growable(limit: usize) -> Self {
+        Self {
+            cells: Cells::Heap(vec![0; GROWABLE_START.min(limit)]),
+            pointer: 0,
+            limit: Some(limit),
+        }
+    }
+
+    /// The cap of a growable tape, or `None` if the tape is fixed.
+    pub fn limit(&self) -> Option<usize> {
+        self.limit
+    }
+
+    /// Grows a growable tape so that the cell `error` found right of its end is on it,
+    /// returning that cell. At least doubles the heap each time, and checks the limit
+    /// before reallocating. Any other error, or a fixed tape, hands `error` back.
+    pub(crate) fn grow_past(&mut self, error: ExecError) -> Result<usize, ExecError> {
+        let (limit, pos) = match (self.limit, &error) {
+            (Some(limit), &ExecError::OutOfBounds { pos }) if pos >= 0 => (limit, pos as usize),
+            _ => return Err(error),
+        };
+        if pos >= limit {
+            return Err(ExecError::TapeLimitExceeded { limit });
+        }
+        match &mut self.cells {
+            Cells::Heap(cells) => {
+                let len = (cells.len() * 2).max(pos + 1).min(limit);
+                cells.resize(len, 0);
+            }
+            #[cfg(all(feature = "mmap-tape", unix))]
+            Cells::Mapped(_) => unreachable!("only `growable` sets a limit, on a heap tape."),
+        }
+        Ok(pos)
+    }
+
+    /// A blank tape of `len` cells like this one, growable up to the same limit if this
+    /// one is; `None` unless `len` is this fixed tape's length or within that limit.
+    pub(crate) fn blank(&self, len: usize) -> Option<Self> {
+        match self.limit {
+            Some(limit) if len <= limit => Some(Self {
+                limit: Some(limit),
+                ..Self::zeroed(len)
+            }),
+            None if len == self.cells.len() => Some(Self::zeroed(len)),
+            _ => None,
+        }
+    }
+
+    /// A tape starting with `initial` and zero-filled after it; bytes past `TAPE_SIZE`
+    /// are ignored.
+    pub fn from_cells(initial: &[u8]) -> Self {
+        let mut tape = Self::new();
+        let len = initial.len().min(TAPE_SIZE);
+        tape.cells[..len].copy_from_slice(&initial[..len]);
+        tape
+    }
+
+    pub fn cells(&self) -> &[u8] {
+        &self.cells
+    }
+
+    pub fn cells_mut(&mut self) -> &mut [u8] {
+        &mut self.cells
+    }
+
+    pub fn pointer(&self) -> usize {
+        self.pointer
+    }
+
+    pub fn set_pointer(&mut self, pointer: usize) {
+        self.pointer = pointer;
+    }
+
+    /// Writes the cells in `range` (clamped to the tape) to `writer`, dropping the
+    /// trailing zero cells of that range when `trim` is set.
+    pub fn dump<R: RangeBounds<usize>>(&self, writer: &mut dyn Write, range: R, trim: bool) -> io::Result<()> {
+        let begin = match range.start_bound() {
+            Bound::Included(&n) => n,
+            Bound::Excluded(&n) => n + 1,
+            Bound::Unbounded => 0,
+        }.min(self.cells.len());
+        let end = match range.end_bound() {
+            Bound::Included(&n) => n + 1,
+            Bound::Excluded(&n) => n,
+            Bound::Unbounded => self.cells.len(),
+        }.min(self.cells.len()).max(begin);
+        let mut bytes = &self.cells[begin..end];
+        if trim {
+            let len = bytes.iter().rposition(|&c| c != 0).map_or(0, |i| i + 1);
+            bytes = &bytes[..len];
+        }
+        writer.write_all(bytes)
+    }
+
+    /// How many cells hold each value, counting cells up to the pointer or the last
+    /// non-zero cell, whichever is further, so the zeros of the untouched tail are left out.
+    pub fn histogram(&self) -> [u64; 256] {
+        let last = self.cells.iter().rposition(|&c| c != 0).unwrap_or(0).max(self.pointer);
+        let mut counts = [0; 256];
+        for &cell in &self.cells[..(last + 1).min(self.cells.len())] {
+            counts[cell as usize] += 1;
+        }
+        counts
+    }
+}
+
+impl Default for Tape {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,194 @@
+use std::fmt::Write;
+use crate::{
+    error::{ExecError, ParseError},
+    interpreter::Interpreter,
+    report::ExitReason,
+    tape::{Tape, TAPE_SIZE},
+};
+
+/// How many commands `Interpreter::verify` lets the source run per input.
+const VERIFY_STEPS: u64 = 1 << 20;
+
+/// The token cap of `run_bytes_limited`, under a second of interpretation in a debug build.
+pub const DEFAULT_TEST_STEPS: u64 = 1 << 26;
+
+/// Parses and interprets `program` on `input` like `Interpreter::run_bytes`, but fails
+/// with `ExecError::StepLimit` after `DEFAULT_TEST_STEPS` tokens, so a program that
+/// never ends fails a test instead of hanging it. Panics if `program` does not parse.
+pub fn run_bytes_limited(program: &str, input: &[u8]) -> Result<Vec<u8>, ExecError> {
+    let mut interpreter = Interpreter::new(program.chars()).unwrap_or_else(|e| panic!("run_bytes_limited: {}", e));
+    interpreter.config_mut().max_steps = Some(DEFAULT_TEST_STEPS);
+    interpreter.run_bytes(input)
+}
+
+/// Runs `program` on `input` with the interpreter and panics unless every `(index, value)`
+/// pair in `expected` matches the final tape. The panic message lists the mismatches and
+/// every non-zero cell, so a failing BF unit test shows what the program actually did.
+pub fn assert_tape(program: &str, input: &[u8], expected: &[(usize, u8)]) {
+    let interpreter = Interpreter::new(program.chars()).unwrap_or_else(|e| panic!("assert_tape: {}", e));
+    let tape = interpreter.run_inspect(&mut &input[..], &mut Vec::new())
+        .unwrap_or_else(|e| panic!("assert_tape: {}", e));
+    let mut message = String::new();
+    for &(index, value) in expected {
+        match tape.cells().get(index) {
+            Some(&actual) if actual == value => {}
+            Some(&actual) => writeln!(message, "  cell {}: expected {}, found {}", index, value, actual).unwrap(),
+            None => writeln!(message, "  cell {}: expected {}, but it is outside the tape", index, value).unwrap(),
+        }
+    }
+    if !message.is_empty() {
+        panic!("assert_tape failed:\n{}{}", message, sparse_dump(&tape));
+    }
+}
+
+fn sparse_dump(tape: &Tape) -> String {
+    let mut dump = format!("tape (pointer at {}):\n", tape.pointer());
+    for (index, value) in tape.cells().iter().enumerate().filter(|(_, &v)| v != 0) {
+        writeln!(dump, "  [{}] = {}", index, value).unwrap();
+    }
+    dump
+}
+
+/// A reference interpreter straight over the source text, sharing no code with the
+/// parser, the optimizer or any backend. Cells wrap, input past the end reads as zero,
+/// and running more than `max_steps` commands fails with `ExecError::StepLimit`.
+/// Panics on unbalanced brackets.
+pub fn interpret_naive(program: &str, input: &[u8], max_steps: u64) -> Result<Vec<u8>, ExecError> {
+    let code: Vec<u8> = program.bytes().filter(|b| b"+-<>[].,".contains(b)).collect();
+    let mut jump = vec![0; code.len()];
+    let mut open = Vec::new();
+    for (i, &c) in code.iter().enumerate() {
+        match c {
+            b'[' => open.push(i),
+            b']' => {
+                let begin = open.pop().expect("interpret_naive: [ missing.");
+                jump[begin] = i;
+                jump[i] = begin;
+            }
+            _ => {}
+        }
+    }
+    assert!(open.is_empty(), "interpret_naive: ] missing.");
+    let mut cells = vec![0u8; TAPE_SIZE];
+    let mut input = input.iter();
+    let mut output = Vec::new();
+    let mut pos = 0i32;
+    let mut pc = 0;
+    let mut steps = 0;
+    while pc < code.len() {
+        if steps == max_steps {
+            return Err(ExecError::StepLimit { steps });
+        }
+        steps += 1;
+        match code[pc] {
+            b'>' => pos += 1,
+            b'<' => pos -= 1,
+            c => {
+                if pos < 0 || pos as usize >= TAPE_SIZE {
+                    return Err(ExecError::OutOfBounds { pos });
+                }
+                let cell = &mut cells[pos as usize];
+                match c {
+                    b'+' => *cell = cell.wrapping_add(1),
+                    b'-' => *cell = cell.wrapping_sub(1),
+                    b',' => *cell = input.next().copied().unwrap_or(0),
+                    b'.' => output.push(*cell),
+                    b'[' if *cell == 0 => pc = jump[pc],
+                    b']' if *cell != 0 => pc = jump[pc],
+                    _ => {}
+                }
+            }
+        }
+        pc += 1;
+    }
+    Ok(output)
+}
+
+/// Runs `program` on `input` through `interpret_naive` and then, optimized and
+/// unoptimized, through `run`, the JIT and the threaded backend, panicking unless all
+/// of them print the same bytes. The program must stay on the tape, since the JIT does
+/// not bounds check, and finish within `max_steps` commands. `run` and the threaded
+/// backend stop with `StepLimit` past that many tokens, so a miscompiled loop fails
+/// instead of hanging; the JIT, which cannot count, only runs once `run` has finished.
+pub fn assert_backends_agree(program: &str, input: &[u8], max_steps: u64) {
+    let expected = interpret_naive(program, input, max_steps)
+        .unwrap_or_else(|e| panic!("assert_backends_agree: {}", e));
+    for (name, optimized) in [("optimized", true), ("unoptimized", false)] {
+        let parsed = if optimized {
+            Interpreter::new(program.chars())
+        } else {
+            Interpreter::unoptimized(program.chars())
+        };
+        let mut interpreter = parsed.unwrap_or_else(|e| panic!("assert_backends_agree: {}", e));
+        // No more tokens than commands run, plus the final `End`.
+        interpreter.config_mut().max_steps = Some(max_steps + 1);
+        let mut run = Vec::new();
+        interpreter.run(&mut &input[..], &mut run)
+            .unwrap_or_else(|e| panic!("assert_backends_agree: {} run: {}", name, e));
+        let mut threaded = Vec::new();
+        interpreter.threaded()
+            .unwrap_or_else(|e| panic!("assert_backends_agree: {} threaded: {}", name, e))
+            .run(&mut &input[..], &mut threaded)
+            .unwrap_or_else(|e| panic!("assert_backends_agree: {} threaded: {}", name, e));
+        interpreter.config_mut().max_steps = None;
+        let mut jit = Vec::new();
+        (interpreter.compile())(&mut &input[..], &mut jit);
+        for (backend, output) in [("run", run), ("jit", jit), ("threaded", threaded)] {
+            if output != expected {
+                panic!(
+                    "assert_backends_agree: {} {} printed {:?}, expected {:?}",
+                    name, backend, String::from_utf8_lossy(&output), String::from_utf8_lossy(&expected),
+                );
+            }
+        }
+    }
+}
+
+impl Interpreter {
+    /// Parses `source` and checks the result with `verify`.
+    pub fn new_verified(source: &str) -> Result<Self, ParseError> {
+        let interpreter = Self::new(source.chars())?;
+        interpreter.verify(source)?;
+        Ok(interpreter)
+    }
+
+    /// Runs `source` through `interpret_naive` and this (optimized) program through
+    /// `run` on a few pseudo-random inputs, failing with `ParseError::Diverged` if their
+    /// output differs. Inputs on which the source errors or runs longer than
+    /// `VERIFY_STEPS` commands are skipped. The source reads zero at EOF, so this
+    /// expects the default `EofPolicy::Zero`.
+    pub fn verify(&self, source: &str) -> Result<(), ParseError> {
+        let mut seed = 0x9e37_79b9_7f4a_7c15u64;
+        let mut inputs = vec![Vec::new()];
+        for len in [1, 4, 16, 64] {
+            inputs.push((0..len).map(|_| {
+                seed ^= seed << 13;
+                seed ^= seed >> 7;
+                seed ^= seed << 17;
+                seed as u8
+            }).collect());
+        }
+        for input in inputs {
+            let expected = match interpret_naive(source, &input, VERIFY_STEPS) {
+                Ok(output) => output,
+                Err(_) => continue,
+            };
+            let report = self.report(&input, Some(VERIFY_STEPS), None);
+            if report.exit != ExitReason::Normal || report.output != expected {
+                return Err(ParseError::Diverged { input });
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn run_bytes_limited_stops_an_endless_program() {
+        assert_eq!(run_bytes_limited("+[]", b""), Err(ExecError::StepLimit { steps: DEFAULT_TEST_STEPS }));
+        assert_eq!(run_bytes_limited(",[.,]", b"hi").unwrap(), b"hi");
+    }
+}
\ No newline at end of file
@@ -0,0 +1,332 @@
+use std::io::{Read, Write};
+use crate::{
+    config::{CellArith, ExecConfig},
+    error::ExecError,
+    interpreter::{Interpreter, Token},
+    io::{flush_out, write_out, EofPolicy, FlushPolicy},
+    tape::Tape,
+};
+
+/// Running a loop costs a few native stack frames per active nesting level, so unlike
+/// `run` and `compile`, whose nesting is bounded only by memory, this backend rejects
+/// programs nested deeper than this. It leaves room to spare on the 2 MiB stack of a
+/// spawned thread even in a debug build, where a level takes a few hundred bytes.
+pub const MAX_DEPTH: usize = 2_000;
+
+struct State<'a> {
+    cells: &'a mut [u8],
+    pos: i32,
+    reader: &'a mut dyn Read,
+    writer: &'a mut dyn Write,
+    eof: EofPolicy,
+    flush: FlushPolicy,
+    steps: u64,
+    max_steps: u64,
+}
+
+impl State<'_> {
+    fn cell(&self, shift: i32) -> Result<usize, ExecError> {
+        Interpreter::index(self.pos, shift, self.cells.len())
+    }
+
+    fn step(&mut self) -> Result<(), ExecError> {
+        if self.steps == self.max_steps {
+            return Err(ExecError::StepLimit { steps: self.steps });
+        }
+        self.steps += 1;
+        Ok(())
+    }
+
+    fn output(&mut self, bytes: &[u8]) {
+        write_out(self.writer, bytes).unwrap();
+        if bytes.iter().any(|&byte| self.flush.should_flush(byte)) {
+            flush_out(self.writer).unwrap();
+        }
+    }
+}
+
+type Op = Box<dyn Fn(&mut State, &[Body]) -> Result<(), ExecError>>;
+
+struct Body(Vec<Op>);
+
+/// The token stream pre-compiled into closures, one per token. Each loop body is a
+/// separate closure list that the loop's own closure iterates, so there is no central
+/// `match` and no jump-offset bookkeeping at run time. This is the fallback to reach
+/// for where the x86-64 JIT is unavailable. Cells outside the tape fail with
+/// `OutOfBounds`, and arithmetic and input and output follow the config's
+/// `CellArith`, `EofPolicy` and `FlushPolicy`, as in `run`. Every closure run and every
+/// loop iteration counts as a step against `max_steps`.
+pub struct Threaded {
+    bodies: Vec<Body>,
+    eof: EofPolicy,
+    flush: FlushPolicy,
+    max_steps: Option<u64>,
+}
+
+impl Threaded {
+    pub(crate) fn new(inst: &[Token], config: &ExecConfig) -> Result<Self, &'static str> {
+        let mut bodies = vec![Body(Vec::new())];
+        let mut stack = vec![0];
+        let saturating = config.arith == CellArith::Saturating;
+        for (index, token) in inst.iter().enumerate() {
+            let op: Op = match *token {
+                Token::Add(n, shift) if saturating => Box::new(move |s, _| {
+                    let cell = &mut s.cells[s.cell(shift)?];
+                    *cell = Interpreter::add_cell::<true>(*cell, n as i32);
+                    Ok(())
+                }),
+                Token::Mul(n, shift, base) if saturating => Box::new(move |s, _| {
+                    let base_n = s.cells[s.cell(base)?];
+                    if base_n != 0 {
+                        let cell = &mut s.cells[s.cell(shift)?];
+                        *cell = Interpreter::add_cell::<true>(*cell, base_n as i32 * n as i32);
+                    }
+                    Ok(())
+                }),
+                Token::AddTo(to, from) if saturating => Box::new(move |s, _| {
+                    let from_n = s.cells[s.cell(from)?];
+                    if from_n != 0 {
+                        let cell = &mut s.cells[s.cell(to)?];
+                        *cell = Interpreter::add_cell::<true>(*cell, from_n as i32);
+                    }
+                    Ok(())
+                }),
+                Token::Add(n, shift) => Box::new(move |s, _| {
+                    let cell = &mut s.cells[s.cell(shift)?];
+                    *cell = cell.wrapping_add(n as u8);
+                    Ok(())
+                }),
+                Token::Mul(n, shift, base) => Box::new(move |s, _| {
+                    let base_n = s.cells[s.cell(base)?];
+                    if base_n != 0 {
+                        let cell = &mut s.cells[s.cell(shift)?];
+                        *cell = cell.wrapping_add(base_n.wrapping_mul(n as u8));
+                    }
+                    Ok(())
+                }),
+                Token::AddTo(to, from) => Box::new(move |s, _| {
+                    let from_n = s.cells[s.cell(from)?];
+                    if from_n != 0 {
+                        let cell = &mut s.cells[s.cell(to)?];
+                        *cell = cell.wrapping_add(from_n);
+                    }
+                    Ok(())
+                }),
+                Token::Clear(shift) => Box::new(move |s, _| {
+                    s.cells[s.cell(shift)?] = 0;
+                    Ok(())
+                }),
+                Token::Set(n, shift) => Box::new(move |s, _| {
+                    s.cells[s.cell(shift)?] = n;
+                    Ok(())
+                }),
+                Token::Shift(shift) => Box::new(move |s, _| {
+                    s.pos += shift;
+                    Ok(())
+                }),
+                Token::Scan(n) => Box::new(move |s, _| {
+                    s.pos = Interpreter::scan(s.cells, s.pos, n)?;
+                    Ok(())
+                }),
+                Token::LoopBegin(_) => {
+                    if stack.len() > MAX_DEPTH {
+                        return Err("loops nested too deeply for the threaded backend.");
+                    }
+                    stack.push(bodies.len());
+                    bodies.push(Body(Vec::new()));
+                    continue;
+                }
+                Token::LoopEnd(_) => {
+                    let body = stack.pop().unwrap();
+                    Box::new(move |s, bodies| {
+                        while s.cells[s.cell(0)?] != 0 {
+                            s.step()?;
+                            for op in &bodies[body].0 {
+                                s.step()?;
+                                op(s, bodies)?;
+                            }
+                        }
+                        Ok(())
+                    })
+                }
+                Token::Input(shift) => Box::new(move |s, _| {
+                    let cell = s.cell(shift)?;
+                    s.cells[cell] = s.eof.read(s.reader).ok_or(ExecError::NeedsInput { index })?;
+                    Ok(())
+                }),
+                Token::Output(shift) => Box::new(move |s, _| {
+                    let byte = s.cells[s.cell(shift)?];
+                    s.output(&[byte]);
+                    Ok(())
+                }),
+                Token::OutputBytes(ref literal) => {
+                    let literal = literal.clone();
+                    Box::new(move |s, _| {
+                        s.output(&literal);
+                        Ok(())
+                    })
+                }
+                Token::Nop => continue,
+                Token::End => break,
+            };
+            bodies[*stack.last().unwrap()].0.push(op);
+        }
+        Ok(Self {
+            bodies,
+            eof: config.eof,
+            flush: config.flush,
+            max_steps: config.max_steps,
+        })
+    }
+
+    pub fn run(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<Tape, ExecError> {
+        let mut tape = Tape::new();
+        let (pos, result) = self.eof.with_reader(reader, |reader| {
+            let mut state = State {
+                cells: tape.cells_mut(),
+                pos: 0,
+                reader,
+                writer: &mut *writer,
+                eof: self.eof,
+                flush: self.flush,
+                steps: 0,
+                max_steps: self.max_steps.unwrap_or(u64::MAX),
+            };
+            let result = self.bodies[0].0.iter().try_for_each(|op| {
+                state.step()?;
+                op(&mut state, &self.bodies)
+            });
+            (state.pos, result)
+        });
+        flush_out(writer).unwrap();
+        result?;
+        tape.set_pointer(pos as usize);
+        Ok(tape)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use super::*;
+
+    struct Flushes {
+        bytes: Vec<u8>,
+        flushes: usize,
+    }
+
+    impl Write for Flushes {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.bytes.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.flushes += 1;
+            Ok(())
+        }
+    }
+
+    fn flushes(flush: FlushPolicy) -> Flushes {
+        let mut program = Interpreter::new(",.,.".chars()).unwrap();
+        program.set_flush_policy(flush);
+        let mut writer = Flushes { bytes: Vec::new(), flushes: 0 };
+        program.threaded().unwrap().run(&mut &b"a\n"[..], &mut writer).unwrap();
+        writer
+    }
+
+    #[test]
+    fn pointer_left_of_the_tape_is_out_of_bounds() {
+        let program = Interpreter::new(",<+".chars()).unwrap();
+        let result = program.threaded().unwrap().run(&mut &b"x"[..], &mut Vec::new());
+        assert_eq!(result.unwrap_err(), ExecError::OutOfBounds { pos: -1 });
+    }
+
+    #[test]
+    fn pointer_right_of_the_tape_is_out_of_bounds() {
+        let program = Interpreter::new("+[>+]".chars()).unwrap();
+        let result = program.threaded().unwrap().run(&mut &b""[..], &mut Vec::new());
+        assert!(matches!(result, Err(ExecError::OutOfBounds { .. })));
+    }
+
+    #[test]
+    fn eof_follows_the_config() {
+        let mut program = Interpreter::new(",.".chars()).unwrap();
+        program.set_eof_policy(EofPolicy::MinusOne);
+        let mut output = Vec::new();
+        let tape = program.threaded().unwrap().run(&mut &b""[..], &mut output).unwrap();
+        assert_eq!(output, [0xff]);
+        assert_eq!(tape.cells()[0], 0xff);
+    }
+
+    #[test]
+    fn endless_loops_stop_at_the_step_limit() {
+        for src in ["+[]", "+[.]"] {
+            let mut program = Interpreter::new(src.chars()).unwrap();
+            program.config_mut().max_steps = Some(1000);
+            let result = program.threaded().unwrap().run(&mut &b""[..], &mut io::sink());
+            assert_eq!(result.unwrap_err(), ExecError::StepLimit { steps: 1000 }, "{}", src);
+        }
+    }
+
+    #[test]
+    fn blocking_reader_needs_input() {
+        struct Blocking;
+        impl Read for Blocking {
+            fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+                Err(io::ErrorKind::WouldBlock.into())
+            }
+        }
+        let program = Interpreter::new(",.".chars()).unwrap();
+        let result = program.threaded().unwrap().run(&mut Blocking, &mut Vec::new());
+        assert!(matches!(result, Err(ExecError::NeedsInput { .. })));
+    }
+
+    #[test]
+    fn output_is_flushed_per_the_config() {
+        let per_char = flushes(FlushPolicy::PerChar);
+        assert_eq!(per_char.bytes, b"a\n");
+        assert_eq!(per_char.flushes, 3);
+        assert_eq!(flushes(FlushPolicy::PerNewline).flushes, 2);
+        assert_eq!(flushes(FlushPolicy::AtEnd).flushes, 1);
+    }
+
+    #[test]
+    fn output_is_flushed_on_error() {
+        let mut program = Interpreter::new("+.<+".chars()).unwrap();
+        program.set_flush_policy(FlushPolicy::AtEnd);
+        let mut writer = Flushes { bytes: Vec::new(), flushes: 0 };
+        assert!(program.threaded().unwrap().run(&mut &b""[..], &mut writer).is_err());
+        assert_eq!(writer.bytes, [1]);
+        assert_eq!(writer.flushes, 1);
+    }
+
+    fn nested(depth: usize) -> Interpreter {
+        let src = format!("+{}-.{}+.", "[".repeat(depth), "]".repeat(depth));
+        Interpreter::new(src.chars()).unwrap()
+    }
+
+    #[test]
+    fn loops_nested_max_depth_deep_run_in_every_backend() {
+        let program = nested(MAX_DEPTH);
+        assert_eq!(program.run_bytes(b"").unwrap(), [0, 1]);
+        let mut jit = Vec::new();
+        program.compile()(&mut io::empty(), &mut jit);
+        assert_eq!(jit, [0, 1]);
+        let mut threaded = Vec::new();
+        program.threaded().unwrap().run(&mut io::empty(), &mut threaded).unwrap();
+        assert_eq!(threaded, [0, 1]);
+    }
+
+    #[test]
+    fn loops_nested_deeper_are_rejected_by_the_threaded_backend_alone() {
+        assert!(nested(MAX_DEPTH + 1).threaded().is_err());
+        let program = nested(10_000);
+        assert!(program.threaded().is_err());
+        assert_eq!(program.run_bytes(b"").unwrap(), [0, 1]);
+        let mut jit = Vec::new();
+        program.compile()(&mut io::empty(), &mut jit);
+        assert_eq!(jit, [0, 1]);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,120 @@
+use std::{
+    collections::HashSet,
+    convert::TryFrom,
+    io::{Read, Write},
+};
+use crate::{
+    error::ExecError,
+    interpreter::{Interpreter, Meter, Token},
+    tape::Tape,
+};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+/// One access to a watched cell. `value` is the cell as read, or as left by the write.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct WatchHit {
+    /// Index of the token in `Interpreter::tokens`, or in the program as optimized for
+    /// the configured tape when there is one.
+    pub token: usize,
+    pub cell: usize,
+    pub access: Access,
+    pub value: u8,
+}
+
+/// The offsets of the cells `token` reads and writes, if any.
+pub(crate) fn accesses(token: &Token) -> (Option<i32>, Option<i32>) {
+    match *token {
+        Token::Add(_, shift) | Token::Clear(shift) | Token::Set(_, shift) | Token::Input(shift) => (None, Some(shift)),
+        Token::Mul(_, shift, base) => (Some(base), Some(shift)),
+        Token::AddTo(to, from) => (Some(from), Some(to)),
+        Token::LoopBegin(_) | Token::LoopEnd(_) | Token::Scan(_) => (Some(0), None),
+        Token::Output(shift) => (Some(shift), None),
+        Token::Shift(_) | Token::OutputBytes(_) | Token::Nop | Token::End => (None, None),
+    }
+}
+
+pub(crate) struct Watch<'a> {
+    cells: HashSet<i32>,
+    on_hit: &'a mut dyn FnMut(WatchHit),
+}
+
+impl Watch<'_> {
+    /// Reports the cells `token` reads before it runs, or those it writes after. An
+    /// access off the tape is left for the interpreter to fail on.
+    pub fn check(&mut self, index: i32, token: &Token, pos: i32, cells: &[u8], access: Access) {
+        let (read, written) = accesses(token);
+        let shift = match access {
+            Access::Read => read,
+            // `Mul` and `AddTo` leave their target alone while the counter is zero.
+            Access::Write if matches!(token, Token::Mul(..) | Token::AddTo(..))
+                && read.and_then(|base| cells.get((pos + base) as usize)) == Some(&0) => None,
+            Access::Write => written,
+        };
+        let cell = match shift.map(|shift| pos + shift).filter(|cell| self.cells.contains(cell)) {
+            Some(cell) => cell as usize,
+            None => return,
+        };
+        if let Some(&value) = cells.get(cell) {
+            (self.on_hit)(WatchHit {
+                token: index as usize,
+                cell,
+                access,
+                value,
+            });
+        }
+    }
+}
+
+impl Interpreter {
+    /// Like `run_inspect`, but calls `on_hit` whenever a token reads or writes one of
+    /// `cells`. Loop tests count as reads of the current cell. Cells off the tape are
+    /// never hit.
+    pub fn run_watched(&self, cells: &[usize], reader: &mut dyn Read, writer: &mut dyn Write, mut on_hit: impl FnMut(WatchHit)) -> Result<Tape, ExecError> {
+        let mut meter = Meter {
+            watch: Some(Watch {
+                cells: cells.iter().filter_map(|&cell| i32::try_from(cell).ok()).collect(),
+                on_hit: &mut on_hit,
+            }),
+            ..Meter::default()
+        };
+        let config = self.config();
+        let mut tape = config.tape.clone().unwrap_or_default();
+        self.for_tape(config).interpret(config, &mut tape, reader, writer, &mut meter)?;
+        Ok(tape)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use super::*;
+
+    fn hits(program: &Interpreter, cells: &[usize]) -> (Result<Tape, ExecError>, Vec<WatchHit>) {
+        let mut hits = Vec::new();
+        let result = program.run_watched(cells, &mut io::empty(), &mut io::sink(), |hit| hits.push(hit));
+        (result, hits)
+    }
+
+    #[test]
+    fn reads_and_writes_are_reported() {
+        let program = Interpreter::new(">+.".chars()).unwrap();
+        let (result, hits) = hits(&program, &[1]);
+        result.unwrap();
+        let accesses: Vec<_> = hits.iter().map(|hit| (hit.cell, hit.access, hit.value)).collect();
+        assert_eq!(accesses, [(1, Access::Write, 1), (1, Access::Read, 1)]);
+    }
+
+    #[test]
+    fn watch_off_the_tape_fails_like_the_interpreter() {
+        let mut program = Interpreter::new(">>.".chars()).unwrap();
+        program.config_mut().tape = Some(Tape::zeroed(2));
+        let (result, hits) = hits(&program, &[2, usize::MAX]);
+        assert!(matches!(result, Err(ExecError::OutOfBounds { pos: 2 })));
+        assert!(hits.is_empty());
+    }
+}